@@ -0,0 +1,233 @@
+use crate::aws::{Credential, Partition};
+use crate::error::{Error, Result};
+use crate::http::api_client::{AcceptType, ApiClient};
+use serde::Deserialize;
+use std::collections::HashMap;
+use url::Url;
+
+/// Console sessions can last between 15 minutes and 12 hours.
+const SESSION_DURATION_RANGE: std::ops::RangeInclusive<i32> = 900..=43200;
+
+/// Where the console opens and how long its session lasts.
+#[derive(Clone, Debug, Default)]
+pub struct ConsoleOptions {
+    service: Option<String>,
+    region: Option<String>,
+    session_duration: Option<i32>,
+    federation_endpoint: Option<String>,
+}
+
+impl ConsoleOptions {
+    /// Generates a new [`ConsoleOptions`], `federation_endpoint` overrides the partition's
+    /// sign-in endpoint.
+    pub fn new(
+        service: Option<String>,
+        region: Option<String>,
+        session_duration: Option<i32>,
+        federation_endpoint: Option<String>,
+    ) -> Result<Self> {
+        if let Some(duration) = session_duration {
+            if !SESSION_DURATION_RANGE.contains(&duration) {
+                return Err(Error::UserInput(format!(
+                    "the console session duration must be between {} and {} seconds",
+                    SESSION_DURATION_RANGE.start(),
+                    SESSION_DURATION_RANGE.end()
+                )));
+            }
+        }
+
+        Ok(Self {
+            service,
+            region,
+            session_duration,
+            federation_endpoint,
+        })
+    }
+
+    fn federation_endpoint(&self, partition: Partition) -> String {
+        match &self.federation_endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => format!("https://{}/federation", signin_host(partition)),
+        }
+    }
+
+    /// The console page to open, the console's home page without a service.
+    fn destination(&self, partition: Partition) -> String {
+        let base = format!("https://{}", console_host(partition));
+        match (&self.service, &self.region) {
+            (Some(service), Some(region)) => format!("{base}/{service}/home?region={region}"),
+            (Some(service), None) => format!("{base}/{service}/home"),
+            (None, Some(region)) => format!("{base}/console/home?region={region}"),
+            (None, None) => format!("{base}/"),
+        }
+    }
+}
+
+/// Builds sign-in URLs for the AWS console with the federation endpoint.
+///
+/// <https://docs.aws.amazon.com/IAM/latest/UserGuide/id_roles_providers_enable-console-custom-url.html>
+pub struct ConsoleClient {
+    client: ApiClient,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SigninToken {
+    signin_token: String,
+}
+
+impl ConsoleClient {
+    /// Generates a new [`ConsoleClient`] object.
+    pub fn new() -> Result<ConsoleClient> {
+        let client = ApiClient::new()?;
+        Ok(ConsoleClient { client })
+    }
+
+    /// Exchanges the credential for a sign-in token and returns the URL logging into the console.
+    pub async fn signin_url(
+        &self,
+        credential: &Credential,
+        issuer: &str,
+        options: &ConsoleOptions,
+    ) -> Result<String> {
+        let partition = match credential.role_arn() {
+            Some(role_arn) => Partition::from_arn(&role_arn)?,
+            None => Partition::default(),
+        };
+        let endpoint = options.federation_endpoint(partition);
+
+        let session = serde_json::json!({
+            "sessionId": credential.access_key_id(),
+            "sessionKey": credential.secret_access_key(),
+            "sessionToken": credential.session_token(),
+        });
+        let mut params = HashMap::new();
+        params.insert(String::from("Action"), String::from("getSigninToken"));
+        params.insert(String::from("Session"), session.to_string());
+        if let Some(duration) = options.session_duration {
+            params.insert(String::from("SessionDuration"), duration.to_string());
+        }
+
+        let response = self
+            .client
+            .get(endpoint.clone(), Some(params), None, AcceptType::Json)
+            .await?;
+        let body = response.text().await?;
+        let token: SigninToken = serde_json::from_str(body.as_str()).map_err(|_| {
+            Error::UnexpectedResponse(String::from(
+                "the federation endpoint sent no sign-in token",
+            ))
+        })?;
+
+        let mut url = Url::parse(endpoint.as_str())?;
+        url.query_pairs_mut()
+            .append_pair("Action", "login")
+            .append_pair("Issuer", issuer)
+            .append_pair("Destination", options.destination(partition).as_str())
+            .append_pair("SigninToken", token.signin_token.as_str());
+
+        Ok(url.to_string())
+    }
+}
+
+fn signin_host(partition: Partition) -> &'static str {
+    match partition {
+        Partition::Aws => "signin.aws.amazon.com",
+        Partition::AwsUsGov => "signin.amazonaws-us-gov.com",
+        Partition::AwsCn => "signin.amazonaws.cn",
+    }
+}
+
+fn console_host(partition: Partition) -> &'static str {
+    match partition {
+        Partition::Aws => "console.aws.amazon.com",
+        Partition::AwsUsGov => "console.amazonaws-us-gov.com",
+        Partition::AwsCn => "console.amazonaws.cn",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    fn credential() -> Credential {
+        Credential {
+            secret_access_key: String::from("TheSecretAccessKey"),
+            access_key_id: String::from("TheAccessKeyId"),
+            session_token: String::from("TheSessionToken"),
+            role_arn: Some(String::from("arn:aws:iam::000222111000:role/Admin")),
+            expiration: String::from("2030-01-01T00:00:00Z"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signin_url() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/federation")
+                .query_param("Action", "getSigninToken")
+                .query_param("SessionDuration", "3600")
+                .query_param(
+                    "Session",
+                    r#"{"sessionId":"TheAccessKeyId","sessionKey":"TheSecretAccessKey","sessionToken":"TheSessionToken"}"#,
+                );
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"SigninToken":"TheSigninToken"}"#);
+        });
+
+        let options = ConsoleOptions::new(
+            Some(String::from("ec2")),
+            Some(String::from("eu-west-1")),
+            Some(3600),
+            Some(server.url("/federation")),
+        )
+        .unwrap();
+        let client = ConsoleClient::new().unwrap();
+        let url = client
+            .signin_url(&credential(), "c9s", &options)
+            .await
+            .unwrap();
+
+        mock.assert();
+        let url = Url::parse(url.as_str()).unwrap();
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(url.path(), "/federation");
+        assert_eq!(query["Action"], "login");
+        assert_eq!(query["Issuer"], "c9s");
+        assert_eq!(query["SigninToken"], "TheSigninToken");
+        assert_eq!(
+            query["Destination"],
+            "https://console.aws.amazon.com/ec2/home?region=eu-west-1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_signin_error_hides_session() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method("GET").path("/federation");
+            then.status(400);
+        });
+
+        let options =
+            ConsoleOptions::new(None, None, None, Some(server.url("/federation"))).unwrap();
+        let client = ConsoleClient::new().unwrap();
+        let error = client
+            .signin_url(&credential(), "c9s", &options)
+            .await
+            .unwrap_err();
+
+        let text = format!("{error} {error:?}");
+        assert!(matches!(error, Error::HttpStatus { code: 400, .. }));
+        assert!(!text.contains("TheSessionToken"));
+        assert!(!text.contains("TheSecretAccessKey"));
+    }
+
+    #[test]
+    fn test_invalid_session_duration() {
+        assert!(ConsoleOptions::new(None, None, Some(60), None).is_err());
+    }
+}
@@ -1,7 +1,13 @@
-use anyhow::{anyhow, Result};
+use crate::error::{Error, Result};
+use clap::ValueEnum;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::{Duration, OffsetDateTime};
 
+pub mod console;
+pub mod role_filter;
+pub mod shared_files;
 mod sso_portal_api;
 pub mod sso_portal_client;
 pub mod sts;
@@ -18,6 +24,142 @@ impl Account {
     pub fn account_id(&self) -> String {
         self.account_id.clone()
     }
+
+    pub fn account_name(&self) -> String {
+        self.account_name.clone()
+    }
+
+    pub fn email_address(&self) -> String {
+        self.email_address.clone()
+    }
+}
+
+/// The AWS partitions c9s can generate credentials for.
+#[derive(ValueEnum, PartialEq, Eq, Debug, Clone, Copy, Deserialize, Serialize, Default)]
+pub enum Partition {
+    #[serde(rename = "aws")]
+    #[default]
+    Aws,
+    #[serde(rename = "aws-us-gov")]
+    AwsUsGov,
+    #[serde(rename = "aws-cn")]
+    AwsCn,
+}
+
+impl Partition {
+    /// The name of the partition as used in ARNs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Partition::Aws => "aws",
+            Partition::AwsUsGov => "aws-us-gov",
+            Partition::AwsCn => "aws-cn",
+        }
+    }
+
+    /// Finds the partition from the name used in ARNs.
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "aws" => Ok(Partition::Aws),
+            "aws-us-gov" => Ok(Partition::AwsUsGov),
+            "aws-cn" => Ok(Partition::AwsCn),
+            _ => Err(Error::Config(format!("unknown partition {name}"))),
+        }
+    }
+
+    /// Finds the partition of an ARN.
+    pub fn from_arn(arn: &str) -> Result<Self> {
+        let name = arn
+            .strip_prefix("arn:")
+            .and_then(|arn| arn.split(':').next())
+            .ok_or_else(|| Error::InvalidArn(arn.to_string()))?;
+
+        Partition::from_name(name)
+    }
+
+    /// The region used for STS when none is configured.
+    pub fn default_sts_region(&self) -> &'static str {
+        match self {
+            Partition::Aws => "eu-central-1",
+            Partition::AwsUsGov => "us-gov-west-1",
+            Partition::AwsCn => "cn-north-1",
+        }
+    }
+
+    /// The domain suffix of the partition's endpoints.
+    pub fn dns_suffix(&self) -> &'static str {
+        match self {
+            Partition::Aws | Partition::AwsUsGov => "amazonaws.com",
+            Partition::AwsCn => "amazonaws.com.cn",
+        }
+    }
+}
+
+/// The location of the AWS SSO portal API.
+#[derive(Clone, Debug)]
+pub struct SsoPortalEndpoint {
+    region: String,
+    partition: Partition,
+    portal_url: Option<String>,
+}
+
+impl SsoPortalEndpoint {
+    /// Generates a new [`SsoPortalEndpoint`], `portal_url` overrides the url derived from the region
+    /// and the partition, e.g. for VPC endpoints.
+    pub fn new(region: String, partition: Partition, portal_url: Option<String>) -> Self {
+        Self {
+            region,
+            partition,
+            portal_url,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        match &self.portal_url {
+            Some(portal_url) => portal_url.clone(),
+            None => format!(
+                "https://portal.sso.{}.{}",
+                self.region,
+                self.partition.dns_suffix()
+            ),
+        }
+    }
+
+    pub fn partition(&self) -> Partition {
+        self.partition
+    }
+}
+
+/// The bearer token of the AWS SSO portal, which is valid for all accounts and roles of a user.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SsoPortalToken {
+    token: String,
+    expires_at: String,
+}
+
+impl SsoPortalToken {
+    pub fn new(token: String, expires_at: OffsetDateTime) -> Result<Self> {
+        let expires_at = expires_at
+            .format(&Rfc3339)
+            .map_err(|e| Error::UnexpectedResponse(e.to_string()))?;
+
+        Ok(Self { token, expires_at })
+    }
+
+    pub fn token(&self) -> String {
+        self.token.clone()
+    }
+
+    pub fn expires_at(&self) -> String {
+        self.expires_at.clone()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        match OffsetDateTime::parse(self.expires_at.as_str(), &Rfc3339) {
+            Ok(expires_at) => expires_at <= OffsetDateTime::now_utc(),
+            Err(_) => true,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -25,11 +167,61 @@ impl Account {
 pub struct Role {
     account_id: String,
     role_name: String,
+    #[serde(skip)]
+    partition: Partition,
+    #[serde(skip)]
+    account_name: Option<String>,
+    #[serde(skip)]
+    account_email: Option<String>,
+    #[serde(skip)]
+    principal_arn: Option<String>,
 }
 
 impl Role {
     pub fn role_arn(&self) -> String {
-        format!("arn:aws:iam::{}:role/{}", self.account_id, self.role_name)
+        format!(
+            "arn:{}:iam::{}:role/{}",
+            self.partition.name(),
+            self.account_id,
+            self.role_name
+        )
+    }
+
+    pub fn partition(&self) -> Partition {
+        self.partition
+    }
+
+    /// Returns the same role in another partition.
+    pub fn with_partition(mut self, partition: Partition) -> Self {
+        self.partition = partition;
+        self
+    }
+
+    /// The name of the role's account, only AWS SSO knows it.
+    pub fn account_name(&self) -> Option<String> {
+        self.account_name.clone()
+    }
+
+    /// The email address of the role's account, only AWS SSO knows it.
+    pub fn account_email(&self) -> Option<String> {
+        self.account_email.clone()
+    }
+
+    /// Returns the same role with the details of its account.
+    pub fn with_account(mut self, account: &Account) -> Self {
+        self.account_name = Some(account.account_name());
+        self.account_email = Some(account.email_address());
+        self
+    }
+
+    /// The ARN of the SAML provider used to assume the role, only for Okta's AWS application.
+    pub fn principal_arn(&self) -> Option<String> {
+        self.principal_arn.clone()
+    }
+
+    pub fn with_principal_arn(mut self, principal_arn: String) -> Self {
+        self.principal_arn = Some(principal_arn);
+        self
     }
 
     pub fn role_name(&self) -> String {
@@ -41,26 +233,37 @@ impl Role {
     }
 
     pub fn from_arn(arn: &str) -> Result<Self> {
-        let re = Regex::new(r"arn:aws:iam::([0-9]*):role/(.*)")?;
+        let re = Regex::new(r"arn:(aws|aws-us-gov|aws-cn):iam::([0-9]*):role/(.*)")
+            .map_err(|e| Error::InvalidArn(e.to_string()))?;
         let captures = re
             .captures(arn)
-            .ok_or_else(|| anyhow!("arn could not be parsed"))?;
+            .ok_or_else(|| Error::InvalidArn(arn.to_string()))?;
 
-        let account_id = captures
+        let partition = captures
             .get(1)
-            .ok_or_else(|| anyhow!("could not parse account id"))?
+            .ok_or_else(|| Error::InvalidArn(arn.to_string()))?
+            .as_str();
+        let partition = Partition::from_name(partition)?;
+
+        let account_id = captures
+            .get(2)
+            .ok_or_else(|| Error::InvalidArn(arn.to_string()))?
             .as_str()
             .to_string();
 
         let role_name = captures
-            .get(2)
-            .ok_or_else(|| anyhow!("could not parse role name"))?
+            .get(3)
+            .ok_or_else(|| Error::InvalidArn(arn.to_string()))?
             .as_str()
             .to_string();
 
         Ok(Self {
             account_id,
             role_name,
+            partition,
+            account_name: None,
+            account_email: None,
+            principal_arn: None,
         })
     }
 }
@@ -95,6 +298,15 @@ impl Credential {
     pub fn expiration(&self) -> String {
         self.expiration.clone()
     }
+
+    /// Whether the credential expires within `duration`, credentials with an unreadable
+    /// expiration are treated as expired.
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        match OffsetDateTime::parse(self.expiration.as_str(), &Rfc3339) {
+            Ok(expiration) => expiration - OffsetDateTime::now_utc() < duration,
+            Err(_) => true,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -111,5 +323,71 @@ mod tests {
 
         assert_eq!(parsed.account_id, account_id);
         assert_eq!(parsed.role_name, role_name);
+        assert_eq!(parsed.partition, Partition::Aws);
+    }
+
+    #[test]
+    fn test_parse_role_arn_in_other_partitions() {
+        let arn = "arn:aws-us-gov:iam::000222111000:role/Admin";
+        let parsed = Role::from_arn(arn).unwrap();
+
+        assert_eq!(parsed.partition, Partition::AwsUsGov);
+        assert_eq!(parsed.role_arn(), arn);
+
+        let parsed = Role::from_arn("arn:aws-cn:iam::000222111000:role/Admin").unwrap();
+        assert_eq!(parsed.partition, Partition::AwsCn);
+    }
+
+    #[test]
+    fn test_sso_portal_token_expiry() {
+        let now = OffsetDateTime::now_utc();
+        let token =
+            SsoPortalToken::new(String::from("TheToken"), now + Duration::hours(1)).unwrap();
+        assert!(!token.is_expired());
+
+        let token =
+            SsoPortalToken::new(String::from("TheToken"), now - Duration::hours(1)).unwrap();
+        assert!(token.is_expired());
+    }
+
+    #[test]
+    fn test_credential_expires_within() {
+        let credential = |expiration: String| Credential {
+            secret_access_key: String::from("TheSecretAccessKey"),
+            access_key_id: String::from("TheAccessKeyId"),
+            session_token: String::from("TheSessionToken"),
+            role_arn: None,
+            expiration,
+        };
+        let in_five_minutes = (OffsetDateTime::now_utc() + Duration::minutes(5))
+            .format(&Rfc3339)
+            .unwrap();
+
+        assert!(credential(in_five_minutes.clone()).expires_within(Duration::minutes(10)));
+        assert!(!credential(in_five_minutes).expires_within(Duration::minutes(1)));
+        assert!(credential(String::new()).expires_within(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_sso_portal_endpoint() {
+        let endpoint = SsoPortalEndpoint::new(String::from("cn-north-1"), Partition::AwsCn, None);
+        assert_eq!(
+            endpoint.url(),
+            "https://portal.sso.cn-north-1.amazonaws.com.cn"
+        );
+
+        let endpoint = SsoPortalEndpoint::new(
+            String::from("eu-central-1"),
+            Partition::Aws,
+            Some(String::from("https://sso.example.com")),
+        );
+        assert_eq!(endpoint.url(), "https://sso.example.com");
+    }
+
+    #[test]
+    fn test_parse_invalid_role_arn() {
+        let parsed = Role::from_arn("arn:aws:iam::000222111000:user/the-user");
+
+        assert!(matches!(parsed, Err(Error::InvalidArn(_))));
     }
 }
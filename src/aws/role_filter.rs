@@ -0,0 +1,169 @@
+use crate::aws::Role;
+use crate::error::{Error, Result};
+use regex::Regex;
+use std::collections::BTreeMap;
+
+/// A glob like `*:role/ReadOnly`, or a regular expression prefixed with `re:`.
+///
+/// Globs match the whole value, `*` matches any characters and `?` a single one. Regular
+/// expressions match anywhere in the value, unless they're anchored.
+#[derive(Clone, Debug)]
+pub struct RolePattern {
+    regex: Regex,
+}
+
+impl RolePattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let regex = match pattern.strip_prefix("re:") {
+            Some(regex) => regex.to_string(),
+            None => format!(
+                "^{}$",
+                regex::escape(pattern)
+                    .replace("\\*", ".*")
+                    .replace("\\?", ".")
+            ),
+        };
+        let regex = Regex::new(regex.as_str())
+            .map_err(|e| Error::UserInput(format!("invalid pattern {pattern}: {e}")))?;
+
+        Ok(Self { regex })
+    }
+
+    /// Whether `value` is meant as a pattern, rather than as a plain name.
+    pub fn is_pattern(value: &str) -> bool {
+        value.starts_with("re:") || value.contains(['*', '?'])
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        self.regex.is_match(value)
+    }
+}
+
+/// Narrows down the roles of a user, all given patterns have to match.
+#[derive(Clone, Debug, Default)]
+pub struct RoleFilter {
+    role: Option<RolePattern>,
+    account: Option<RolePattern>,
+    role_name: Option<RolePattern>,
+    account_aliases: BTreeMap<String, String>,
+}
+
+impl RoleFilter {
+    /// Generates a new [`RoleFilter`].
+    ///
+    /// `role` matches the role ARN or `account-alias/role-name`, `account` matches the account
+    /// id, name or alias, and `role_name` the name of the role.
+    pub fn new(role: Option<&str>, account: Option<&str>, role_name: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            role: role.map(RolePattern::new).transpose()?,
+            account: account.map(RolePattern::new).transpose()?,
+            role_name: role_name.map(RolePattern::new).transpose()?,
+            account_aliases: BTreeMap::new(),
+        })
+    }
+
+    /// The aliases of accounts by their id, which the patterns match as well.
+    pub fn with_account_aliases(mut self, account_aliases: BTreeMap<String, String>) -> Self {
+        self.account_aliases = account_aliases;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.role.is_none() && self.account.is_none() && self.role_name.is_none()
+    }
+
+    pub fn matches(&self, role: &Role) -> bool {
+        let alias = self.account_aliases.get(&role.account_id());
+
+        let role_matches = self.role.as_ref().is_none_or(|pattern| {
+            pattern.is_match(&role.role_arn())
+                || alias.is_some_and(|alias| {
+                    pattern.is_match(&format!("{}/{}", alias, role.role_name()))
+                })
+        });
+        let account_matches = self.account.as_ref().is_none_or(|pattern| {
+            pattern.is_match(&role.account_id())
+                || role
+                    .account_name()
+                    .is_some_and(|name| pattern.is_match(&name))
+                || alias.is_some_and(|alias| pattern.is_match(alias))
+        });
+        let role_name_matches = self
+            .role_name
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(&role.role_name()));
+
+        role_matches && account_matches && role_name_matches
+    }
+
+    /// The matching roles, it's an error when none of them match.
+    pub fn apply(&self, roles: Vec<Role>) -> Result<Vec<Role>> {
+        let roles: Vec<Role> = roles
+            .into_iter()
+            .filter(|role| self.matches(role))
+            .collect();
+        if roles.is_empty() {
+            return Err(Error::RoleNotFound(String::from("matching the filters")));
+        }
+
+        Ok(roles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roles() -> Vec<Role> {
+        vec![
+            Role::from_arn("arn:aws:iam::000222111000:role/ReadOnly").unwrap(),
+            Role::from_arn("arn:aws:iam::000222111000:role/Admin").unwrap(),
+            Role::from_arn("arn:aws:iam::000333111000:role/ReadOnly").unwrap(),
+        ]
+    }
+
+    fn role_arns(roles: Vec<Role>) -> Vec<String> {
+        roles.iter().map(Role::role_arn).collect()
+    }
+
+    #[test]
+    fn test_glob_and_regex_patterns() {
+        let glob = RolePattern::new("*:role/Read?nly").unwrap();
+        assert!(glob.is_match("arn:aws:iam::000222111000:role/ReadOnly"));
+        assert!(!glob.is_match("arn:aws:iam::000222111000:role/ReadOnlyAudit"));
+
+        let regex = RolePattern::new("re:role/(Admin|ReadOnly)$").unwrap();
+        assert!(regex.is_match("arn:aws:iam::000222111000:role/Admin"));
+        assert!(!regex.is_match("arn:aws:iam::000222111000:role/Audit"));
+
+        assert!(RolePattern::new("re:(").is_err());
+        assert!(RolePattern::is_pattern("*:role/ReadOnly"));
+        assert!(!RolePattern::is_pattern("prod/Admin"));
+    }
+
+    #[test]
+    fn test_filter_roles() {
+        let filter = RoleFilter::new(Some("*:role/ReadOnly"), None, None).unwrap();
+        assert_eq!(
+            role_arns(filter.apply(roles()).unwrap()),
+            vec![
+                "arn:aws:iam::000222111000:role/ReadOnly",
+                "arn:aws:iam::000333111000:role/ReadOnly"
+            ]
+        );
+
+        let filter = RoleFilter::new(None, Some("prod"), Some("ReadOnly"))
+            .unwrap()
+            .with_account_aliases(BTreeMap::from([(
+                String::from("000333111000"),
+                String::from("prod"),
+            )]));
+        assert_eq!(
+            role_arns(filter.apply(roles()).unwrap()),
+            vec!["arn:aws:iam::000333111000:role/ReadOnly"]
+        );
+
+        let filter = RoleFilter::new(None, Some("0004*"), None).unwrap();
+        assert!(filter.apply(roles()).is_err());
+    }
+}
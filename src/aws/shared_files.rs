@@ -0,0 +1,380 @@
+use crate::aws::{Credential, Role};
+use crate::error::{Error, Result};
+use crate::files::{self, write_atomically};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// The AWS shared credentials or config file.
+///
+/// Updates are done while holding a lock on a sidecar `.lock` file and the new contents are
+/// written to a temporary file which then replaces the original one, so concurrent runs of c9s
+/// can't corrupt the file.
+pub struct SharedFile {
+    path: PathBuf,
+}
+
+impl SharedFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The shared credentials file, honoring `AWS_SHARED_CREDENTIALS_FILE`.
+    pub fn credentials() -> Result<Self> {
+        Self::from_env("AWS_SHARED_CREDENTIALS_FILE", "credentials")
+    }
+
+    /// The shared config file, honoring `AWS_CONFIG_FILE`.
+    pub fn config() -> Result<Self> {
+        Self::from_env("AWS_CONFIG_FILE", "config")
+    }
+
+    fn from_env(variable: &str, file_name: &str) -> Result<Self> {
+        let path = match std::env::var_os(variable) {
+            Some(path) => PathBuf::from(path),
+            None => dirs::home_dir()
+                .ok_or_else(|| Error::Config(String::from("could not determine home directory")))?
+                .join(".aws")
+                .join(file_name),
+        };
+
+        Ok(Self::new(path))
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Reads the current contents of the file, an empty document if it doesn't exist yet.
+    pub fn read(&self) -> Result<IniDocument> {
+        if !self.path.exists() {
+            return Ok(IniDocument::default());
+        }
+
+        let contents = fs::read_to_string(&self.path)?;
+
+        Ok(IniDocument::parse(contents.as_str()))
+    }
+
+    /// Applies `update` to the contents of the file and writes the result back.
+    pub fn update<F>(&self, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut IniDocument) -> Result<()>,
+    {
+        let directory = self
+            .path
+            .parent()
+            .ok_or_else(|| Error::Config(format!("{:?} has no parent directory", self.path)))?;
+        fs::create_dir_all(directory)?;
+
+        files::locked(&self.path, || {
+            let mut document = self.read()?;
+            update(&mut document)?;
+            write_atomically(&self.path, document.to_string().as_bytes())
+        })
+    }
+
+    /// Writes a credential for every profile name, leaving all other sections untouched.
+    pub fn set_credentials(&self, credentials: &[(String, Credential)]) -> Result<()> {
+        self.update(|document| {
+            for (profile_name, credential) in credentials {
+                document.set_section(
+                    profile_name,
+                    &[
+                        ("aws_access_key_id", credential.access_key_id()),
+                        ("aws_secret_access_key", credential.secret_access_key()),
+                        ("aws_session_token", credential.session_token()),
+                        // the key used by other SAML tools, not read by the AWS SDKs
+                        ("x_security_token_expires", credential.expiration()),
+                    ],
+                );
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// A minimal INI document which keeps every line it doesn't touch, including comments.
+#[derive(Default)]
+pub struct IniDocument {
+    lines: Vec<String>,
+}
+
+impl IniDocument {
+    pub fn parse(contents: &str) -> Self {
+        Self {
+            lines: contents.lines().map(String::from).collect(),
+        }
+    }
+
+    /// The names of all sections, in order.
+    pub fn section_names(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .filter_map(|line| section_name(line))
+            .collect()
+    }
+
+    /// Sets the keys of a section, creating it if it doesn't exist.
+    ///
+    /// Keys which already exist are updated in place, other keys in the section are kept.
+    pub fn set_section(&mut self, name: &str, entries: &[(&str, String)]) {
+        let (start, mut end) = match self.section_range(name) {
+            Some(range) => range,
+            None => {
+                if self
+                    .lines
+                    .last()
+                    .is_some_and(|line| !line.trim().is_empty())
+                {
+                    self.lines.push(String::new());
+                }
+                self.lines.push(format!("[{name}]"));
+                (self.lines.len() - 1, self.lines.len())
+            }
+        };
+
+        // new keys are added after the last non empty line of the section
+        let mut insert_at = (start + 1..end)
+            .rev()
+            .find(|i| !self.lines[*i].trim().is_empty())
+            .map_or(start + 1, |i| i + 1);
+
+        for (key, value) in entries {
+            let line = format!("{key} = {value}");
+            let existing =
+                (start + 1..end).find(|i| key_name(&self.lines[*i]).is_some_and(|k| k == *key));
+
+            match existing {
+                Some(i) => self.lines[i] = line,
+                None => {
+                    self.lines.insert(insert_at, line);
+                    insert_at += 1;
+                    end += 1;
+                }
+            }
+        }
+    }
+
+    /// The non empty lines of a section, without its header.
+    pub fn section(&self, name: &str) -> Option<Vec<String>> {
+        let (start, end) = self.section_range(name)?;
+
+        Some(
+            self.lines[start + 1..end]
+                .iter()
+                .filter(|line| !line.trim().is_empty())
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// The lines of a block managed by c9s, without its markers.
+    pub fn managed_block(&self, id: &str) -> Option<Vec<String>> {
+        let (start, end) = self.managed_block_range(id)?;
+
+        Some(self.lines[start + 1..end].to_vec())
+    }
+
+    /// Replaces the lines of a block managed by c9s, the block is appended if it doesn't exist.
+    pub fn set_managed_block(&mut self, id: &str, lines: Vec<String>) {
+        let (begin, end) = managed_block_markers(id);
+        let mut block = vec![begin];
+        block.extend(lines);
+        block.push(end);
+
+        match self.managed_block_range(id) {
+            Some((start, end)) => {
+                self.lines.splice(start..=end, block);
+            }
+            None => {
+                if self
+                    .lines
+                    .last()
+                    .is_some_and(|line| !line.trim().is_empty())
+                {
+                    self.lines.push(String::new());
+                }
+                self.lines.extend(block);
+            }
+        }
+    }
+
+    /// The names of all sections outside of the managed block.
+    pub fn unmanaged_section_names(&self, id: &str) -> Vec<String> {
+        let range = self.managed_block_range(id);
+
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| range.is_none_or(|(start, end)| *i < start || *i > end))
+            .filter_map(|(_, line)| section_name(line))
+            .collect()
+    }
+
+    /// The indexes of the begin and the end marker of a managed block.
+    fn managed_block_range(&self, id: &str) -> Option<(usize, usize)> {
+        let (begin, end) = managed_block_markers(id);
+        let start = self.lines.iter().position(|line| line.trim() == begin)?;
+        let end = start
+            + self.lines[start..]
+                .iter()
+                .position(|line| line.trim() == end)?;
+
+        Some((start, end))
+    }
+
+    /// The index of the section header and the index after its last line.
+    fn section_range(&self, name: &str) -> Option<(usize, usize)> {
+        let start = self
+            .lines
+            .iter()
+            .position(|line| section_name(line).is_some_and(|n| n == name))?;
+        let end = self.lines[start + 1..]
+            .iter()
+            .position(|line| section_name(line).is_some() || is_managed_block_marker(line))
+            .map_or(self.lines.len(), |i| start + 1 + i);
+
+        Some((start, end))
+    }
+}
+
+impl fmt::Display for IniDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{line}")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn managed_block_markers(id: &str) -> (String, String) {
+    (
+        format!("# BEGIN c9s managed block: {id}"),
+        format!("# END c9s managed block: {id}"),
+    )
+}
+
+fn is_managed_block_marker(line: &str) -> bool {
+    let line = line.trim();
+
+    line.starts_with("# BEGIN c9s managed block: ") || line.starts_with("# END c9s managed block: ")
+}
+
+fn section_name(line: &str) -> Option<String> {
+    let line = line.trim();
+    let name = line.strip_prefix('[')?.strip_suffix(']')?;
+
+    Some(name.trim().to_string())
+}
+
+fn key_name(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.starts_with('#') || line.starts_with(';') {
+        return None;
+    }
+    let (key, _) = line.split_once('=')?;
+
+    Some(key.trim().to_string())
+}
+
+/// Builds a profile name for a role from a template.
+///
+/// The placeholders `{account_id}` and `{role_name}` are replaced with the values of the role.
+pub fn profile_name(template: &str, role_arn: &str) -> Result<String> {
+    let role = Role::from_arn(role_arn)?;
+
+    Ok(template
+        .replace("{account_id}", role.account_id().as_str())
+        .replace("{role_name}", role.role_name().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_section_keeps_other_lines() {
+        let contents = "# my credentials\n[default]\naws_access_key_id = foo\n\n[other]\n# keep me\naws_access_key_id = old\nregion = eu-west-1\n";
+        let mut document = IniDocument::parse(contents);
+
+        document.set_section("other", &[("aws_access_key_id", String::from("new"))]);
+        document.set_section("new", &[("aws_access_key_id", String::from("bar"))]);
+
+        assert_eq!(
+            document.to_string(),
+            "# my credentials\n[default]\naws_access_key_id = foo\n\n[other]\n# keep me\naws_access_key_id = new\nregion = eu-west-1\n\n[new]\naws_access_key_id = bar\n"
+        );
+    }
+
+    #[test]
+    fn test_set_section_adds_keys_to_end_of_section() {
+        let mut document = IniDocument::parse("[one]\na = 1\n\n[two]\nb = 2\n");
+
+        document.set_section("one", &[("a", String::from("3")), ("c", String::from("4"))]);
+
+        assert_eq!(
+            document.to_string(),
+            "[one]\na = 3\nc = 4\n\n[two]\nb = 2\n"
+        );
+    }
+
+    #[test]
+    fn test_set_managed_block() {
+        let mut document = IniDocument::parse("[default]\nregion = eu-west-1\n");
+
+        document.set_managed_block("host", vec![String::from("[profile one]")]);
+        assert_eq!(
+            document.to_string(),
+            "[default]\nregion = eu-west-1\n\n# BEGIN c9s managed block: host\n[profile one]\n# END c9s managed block: host\n"
+        );
+
+        document.set_managed_block("host", vec![String::from("[profile two]")]);
+        assert_eq!(
+            document.managed_block("host"),
+            Some(vec![String::from("[profile two]")])
+        );
+        assert_eq!(
+            document.unmanaged_section_names("host"),
+            vec![String::from("default")]
+        );
+        assert_eq!(document.managed_block("other"), None);
+    }
+
+    #[test]
+    fn test_profile_name() {
+        let name = profile_name(
+            "{account_id}-{role_name}",
+            "arn:aws:iam::000222111000:role/Admin",
+        )
+        .unwrap();
+
+        assert_eq!(name, "000222111000-Admin");
+    }
+
+    #[test]
+    fn test_set_credentials() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = SharedFile::new(directory.path().join("credentials"));
+        fs::write(file.path(), "[default]\nregion = eu-west-1\n").unwrap();
+
+        let credential = Credential {
+            secret_access_key: String::from("TheSecretAccessKey"),
+            access_key_id: String::from("TheAccessKeyId"),
+            session_token: String::from("TheSessionToken"),
+            role_arn: None,
+            expiration: String::from("2024-01-01T00:00:00Z"),
+        };
+        file.set_credentials(&[(String::from("test"), credential)])
+            .unwrap();
+
+        let document = file.read().unwrap();
+
+        assert_eq!(
+            document.to_string(),
+            "[default]\nregion = eu-west-1\n\n[test]\naws_access_key_id = TheAccessKeyId\naws_secret_access_key = TheSecretAccessKey\naws_session_token = TheSessionToken\nx_security_token_expires = 2024-01-01T00:00:00Z\n"
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use crate::aws::{Account, Credential, Role};
+use crate::error::{Error, Result};
 use crate::http::api_client::{AcceptType, ApiClient};
-use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -56,7 +56,8 @@ impl SsoPortalApi for SsoPortal {
         let response: RoleCredentialResponse = serde_json::from_str(body.as_str())?;
         let time = time::OffsetDateTime::from_unix_timestamp_nanos(
             response.role_credentials.expiration as i128 * 1_000_000i128,
-        )?;
+        )
+        .map_err(|e| Error::UnexpectedResponse(e.to_string()))?;
         let expiration_timestamp = time.format(&Rfc3339).unwrap();
 
         Ok(Credential {
@@ -184,6 +185,7 @@ struct ListAccountRolesResponse {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::aws::Partition;
     use httpmock::MockServer;
 
     #[test]
@@ -227,6 +229,10 @@ mod tests {
         let role = Role {
             account_id: account_id.clone(),
             role_name: role_name.clone(),
+            partition: Partition::Aws,
+            account_name: None,
+            account_email: None,
+            principal_arn: None,
         };
         let client = SsoPortal::new(server.url("")).unwrap();
         let credentials = client.generate_credentials(token, &role).await.unwrap();
@@ -241,6 +247,31 @@ mod tests {
         assert_eq!(credentials.access_key_id, access_key_id);
     }
 
+    #[tokio::test]
+    async fn test_rejected_token_is_not_retried() {
+        let server = MockServer::start();
+        let response_mock = server.mock(|when, then| {
+            when.method("GET").path("/federation/credentials");
+            then.status(401);
+        });
+        let role = Role {
+            account_id: String::from("AccountId"),
+            role_name: String::from("RoleName"),
+            partition: Partition::Aws,
+            account_name: None,
+            account_email: None,
+            principal_arn: None,
+        };
+        let client = SsoPortal::new(server.url("")).unwrap();
+        let error = client
+            .generate_credentials(String::from("TheToken"), &role)
+            .await
+            .unwrap_err();
+
+        response_mock.assert_hits(1);
+        assert!(error.is_unauthorized());
+    }
+
     #[tokio::test]
     async fn test_list_accounts() {
         let token = String::from("TheToken");
@@ -317,6 +348,10 @@ mod tests {
             role_list: vec![Role {
                 account_id: account_id.clone(),
                 role_name: role_name_1.clone(),
+                partition: Partition::Aws,
+                account_name: None,
+                account_email: None,
+                principal_arn: None,
             }],
         };
         let response_2 = ListAccountRolesResponse {
@@ -324,6 +359,10 @@ mod tests {
             role_list: vec![Role {
                 account_id: account_id.clone(),
                 role_name: role_name_2.clone(),
+                partition: Partition::Aws,
+                account_name: None,
+                account_email: None,
+                principal_arn: None,
             }],
         };
         let json_1 = serde_json::to_string(&response_1).unwrap();
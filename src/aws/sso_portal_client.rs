@@ -1,18 +1,20 @@
 use crate::aws::sso_portal_api::{SsoPortal, SsoPortalApi};
-use crate::aws::{Credential, Role};
-use anyhow::Result;
+use crate::aws::{Account, Credential, Partition, Role};
+use crate::error::Result;
 use futures::future;
 
 pub struct SsoPortalClient {
     api_client: Box<dyn SsoPortalApi>,
+    partition: Partition,
 }
 
 impl SsoPortalClient {
-    pub fn new(portal_base_url: String) -> Result<Self> {
+    pub fn new(portal_base_url: String, partition: Partition) -> Result<Self> {
         let api_client = SsoPortal::new(portal_base_url)?;
 
         Ok(SsoPortalClient {
             api_client: Box::new(api_client),
+            partition,
         })
     }
 
@@ -52,14 +54,17 @@ impl SsoPortalClient {
                     .api_client
                     .list_roles(token, account.account_id())
                     .await;
-                RolesFuture { roles }
+                RolesFuture { account, roles }
             }
         }))
         .await;
 
         for future in futures {
-            let mut future_roles = future.roles?;
-            roles.append(&mut future_roles);
+            let future_roles = future.roles?;
+            roles.extend(future_roles.into_iter().map(|role| {
+                role.with_partition(self.partition)
+                    .with_account(&future.account)
+            }));
         }
 
         Ok(roles)
@@ -67,6 +72,7 @@ impl SsoPortalClient {
 }
 
 struct RolesFuture {
+    account: Account,
     roles: Result<Vec<Role>>,
 }
 
@@ -77,7 +83,6 @@ struct CredentialsFuture {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::aws::Account;
     use async_trait::async_trait;
 
     struct SsoPortalApiTest {}
@@ -115,13 +120,17 @@ mod tests {
             Ok(vec![Role {
                 role_name: format!("Role for account {account_id}"),
                 account_id,
+                partition: Partition::Aws,
+                account_name: None,
+                account_email: None,
+                principal_arn: None,
             }])
         }
     }
 
     #[test]
     fn test_new() {
-        let client = SsoPortalClient::new(String::from("https://foo.com"));
+        let client = SsoPortalClient::new(String::from("https://foo.com"), Partition::Aws);
         assert!(client.is_ok());
     }
 
@@ -129,16 +138,25 @@ mod tests {
     async fn test_list_credentials() {
         let client = SsoPortalClient {
             api_client: Box::new(SsoPortalApiTest {}),
+            partition: Partition::Aws,
         };
         let token = String::from("TheToken");
         let roles = vec![
             Role {
                 account_id: String::from("account 1"),
                 role_name: String::from("Role1"),
+                partition: Partition::Aws,
+                account_name: None,
+                account_email: None,
+                principal_arn: None,
             },
             Role {
                 account_id: String::from("account 2"),
                 role_name: String::from("Role2"),
+                partition: Partition::Aws,
+                account_name: None,
+                account_email: None,
+                principal_arn: None,
             },
         ];
         let credentials = client.list_credentials(token, roles).await.unwrap();
@@ -172,6 +190,7 @@ mod tests {
     async fn test_list_role_arns() {
         let client = SsoPortalClient {
             api_client: Box::new(SsoPortalApiTest {}),
+            partition: Partition::Aws,
         };
         let token = String::from("TheToken");
         let role_arns = client.list_role_arns(token).await.unwrap();
@@ -185,5 +204,13 @@ mod tests {
         );
         assert_eq!(role_arns.first().unwrap().account_id, "AccountId1");
         assert_eq!(role_arns.get(1).unwrap().account_id, "AccountId2");
+        assert_eq!(
+            role_arns.get(1).unwrap().account_name(),
+            Some(String::from("account_2"))
+        );
+        assert_eq!(
+            role_arns.get(1).unwrap().account_email(),
+            Some(String::from("account2@foo.com"))
+        );
     }
 }
@@ -1,42 +1,136 @@
-use crate::aws::Credential;
-use anyhow::{anyhow, Result};
+use crate::aws::{Credential, Partition, Role};
+use crate::error::{Error, Result};
 use aws_sdk_sts::config::Region;
+use aws_sdk_sts::error::{ProvideErrorMetadata, SdkError};
 use aws_sdk_sts::operation::assume_role_with_saml::AssumeRoleWithSamlOutput;
+use aws_sdk_sts::types::Tag;
 use aws_smithy_types_convert::date_time::DateTimeExt;
 use futures::future;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use time::format_description::well_known::Rfc3339;
 
-pub struct StsClient {}
+pub struct StsClient {
+    options: StsOptions,
+}
+
+/// Where credentials are requested from STS.
+#[derive(Clone, Debug, Default)]
+pub struct StsOptions {
+    region: Option<String>,
+    endpoint_url: Option<String>,
+    duration: Option<i32>,
+}
+
+/// The session duration STS grants every role, and the longest one for chained roles.
+const DEFAULT_DURATION: i32 = 60 * 60;
+
+/// The session name of chained roles without one.
+const DEFAULT_SESSION_NAME: &str = "c9s";
+
+/// A role assumed with the credentials of the previous role in a chain.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RoleHop {
+    role_arn: String,
+    external_id: Option<String>,
+    session_name: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+}
+
+impl RoleHop {
+    pub fn new(
+        role_arn: String,
+        external_id: Option<String>,
+        session_name: Option<String>,
+        tags: BTreeMap<String, String>,
+    ) -> Result<Self> {
+        Role::from_arn(&role_arn)?;
+
+        Ok(Self {
+            role_arn,
+            external_id,
+            session_name,
+            tags,
+        })
+    }
+
+    pub fn role_arn(&self) -> String {
+        self.role_arn.clone()
+    }
+
+    pub fn external_id(&self) -> Option<String> {
+        self.external_id.clone()
+    }
+
+    pub fn session_name(&self) -> Option<String> {
+        self.session_name.clone()
+    }
+
+    pub fn tags(&self) -> BTreeMap<String, String> {
+        self.tags.clone()
+    }
+}
+
+impl StsOptions {
+    /// Generates new [`StsOptions`].
+    ///
+    /// Without a region, the default region of the role's partition is used. The endpoint url
+    /// overrides the endpoint derived from the region, e.g. for VPC endpoints.
+    pub fn new(region: Option<String>, endpoint_url: Option<String>) -> Self {
+        Self {
+            region,
+            endpoint_url,
+            duration: None,
+        }
+    }
+
+    /// Requests sessions of `duration` seconds, as far as the SAML assertion allows it.
+    pub fn with_duration(mut self, duration: Option<i32>) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// The duration to request, the requested one or an hour, capped by the one allowed in the
+    /// SAML assertion.
+    fn duration(&self, allowed_duration: Option<i32>) -> i32 {
+        let duration = self.duration.unwrap_or(DEFAULT_DURATION);
+
+        allowed_duration.map_or(duration, |allowed| duration.min(allowed))
+    }
+
+    fn region(&self, role_arn: &str) -> String {
+        match &self.region {
+            Some(region) => region.clone(),
+            None => Partition::from_arn(role_arn)
+                .unwrap_or_default()
+                .default_sts_region()
+                .to_string(),
+        }
+    }
+}
 
 impl StsClient {
-    pub fn new() -> Result<Self> {
-        Ok(Self {})
+    pub fn new(options: StsOptions) -> Result<Self> {
+        Ok(Self { options })
     }
 
+    /// Assumes every role with the SAML assertion. `allowed_duration` is the SessionDuration of the
+    /// assertion, if it has one.
     pub async fn generate_sts_credentials(
         &self,
         saml_response: String,
         saml_aws_credentials: Vec<SamlAWSRole>,
+        allowed_duration: Option<i32>,
     ) -> Result<Vec<Credential>> {
+        let duration = self.options.duration(allowed_duration);
         let futures = future::join_all(saml_aws_credentials.into_iter().map(|role| {
-            let config = aws_sdk_sts::Config::builder()
-                .region(Some(Region::new(String::from("eu-central-1"))))
-                .build();
-            let bar = aws_sdk_sts::Client::from_conf(config);
-            let saml = bar
-                .assume_role_with_saml()
-                .set_role_arn(Some(role.role_arn.clone()))
-                .set_saml_assertion(Some(saml_response.clone()))
-                .set_principal_arn(Some(role.principal_arn))
-                .set_duration_seconds(Some(60 * 60))
-                .send();
-
-            let role_arn = role.role_arn;
+            let saml_response = saml_response.clone();
             async move {
-                let response = saml.await.map_err(|e| anyhow!(e.to_string()));
+                let response = self.assume_role(&role, saml_response, duration).await;
 
                 StsFuture {
-                    role_arn,
+                    role_arn: role.role_arn,
                     request: response,
                 }
             }
@@ -47,26 +141,131 @@ impl StsClient {
 
         for future in futures {
             let response = future.request?;
-            let credentials = response
-                .credentials
-                .ok_or_else(|| anyhow!("Could not get credentials from STS"))?;
-            let expiration_timestamp = credentials
-                .expiration
-                .to_time()
-                .unwrap()
-                .format(&Rfc3339)
-                .unwrap();
-            aws_credentials.push(Credential {
-                secret_access_key: credentials.secret_access_key,
-                access_key_id: credentials.access_key_id,
-                role_arn: Some(future.role_arn),
-                session_token: credentials.session_token,
-                expiration: expiration_timestamp,
-            });
+            aws_credentials.push(credential(response.credentials, future.role_arn)?);
         }
 
         Ok(aws_credentials)
     }
+
+    /// Assumes the role of the hop with the credentials of the previous role in the chain.
+    ///
+    /// AWS limits chained sessions to an hour, so longer durations are capped.
+    pub async fn assume_role_chained(
+        &self,
+        source: &Credential,
+        hop: &RoleHop,
+    ) -> Result<Credential> {
+        let credentials = aws_sdk_sts::config::Credentials::new(
+            source.access_key_id(),
+            source.secret_access_key(),
+            Some(source.session_token()),
+            None,
+            "c9s",
+        );
+        let config = self.config(&hop.role_arn).credentials_provider(credentials);
+        let client = aws_sdk_sts::Client::from_conf(config.build());
+
+        let mut tags = vec![];
+        for (key, value) in &hop.tags {
+            let tag = Tag::builder()
+                .key(key)
+                .value(value)
+                .build()
+                .map_err(|e| Error::StsFailure(e.to_string()))?;
+            tags.push(tag);
+        }
+
+        let response = client
+            .assume_role()
+            .role_arn(hop.role_arn.clone())
+            .role_session_name(
+                hop.session_name
+                    .clone()
+                    .unwrap_or_else(|| String::from(DEFAULT_SESSION_NAME)),
+            )
+            .set_external_id(hop.external_id.clone())
+            .set_tags((!tags.is_empty()).then_some(tags))
+            .duration_seconds(self.options.duration(Some(DEFAULT_DURATION)))
+            .send()
+            .await
+            .map_err(|e| Error::StsFailure(e.to_string()))?;
+
+        credential(response.credentials, hop.role_arn.clone())
+    }
+
+    fn config(&self, role_arn: &str) -> aws_sdk_sts::config::Builder {
+        let mut config =
+            aws_sdk_sts::Config::builder().region(Some(Region::new(self.options.region(role_arn))));
+        if let Some(endpoint_url) = &self.options.endpoint_url {
+            config = config.endpoint_url(endpoint_url);
+        }
+
+        config
+    }
+
+    /// Assumes the role, halving the duration while STS rejects it for exceeding the role's
+    /// MaxSessionDuration. Every role allows at least an hour.
+    async fn assume_role(
+        &self,
+        role: &SamlAWSRole,
+        saml_response: String,
+        duration: i32,
+    ) -> Result<AssumeRoleWithSamlOutput> {
+        let client = aws_sdk_sts::Client::from_conf(self.config(&role.role_arn).build());
+
+        let mut duration = duration;
+        loop {
+            let response = client
+                .assume_role_with_saml()
+                .set_role_arn(Some(role.role_arn.clone()))
+                .set_saml_assertion(Some(saml_response.clone()))
+                .set_principal_arn(Some(role.principal_arn.clone()))
+                .set_duration_seconds(Some(duration))
+                .send()
+                .await;
+
+            match response {
+                Ok(response) => return Ok(response),
+                Err(e) if exceeds_max_session_duration(&e) && duration > DEFAULT_DURATION => {
+                    duration = (duration / 2).max(DEFAULT_DURATION);
+                }
+                Err(e) => return Err(Error::StsFailure(e.to_string())),
+            }
+        }
+    }
+}
+
+fn credential(
+    credentials: Option<aws_sdk_sts::types::Credentials>,
+    role_arn: String,
+) -> Result<Credential> {
+    let credentials =
+        credentials.ok_or_else(|| Error::StsFailure(String::from("no credentials in response")))?;
+    let expiration_timestamp = credentials
+        .expiration
+        .to_time()
+        .unwrap()
+        .format(&Rfc3339)
+        .unwrap();
+
+    Ok(Credential {
+        secret_access_key: credentials.secret_access_key,
+        access_key_id: credentials.access_key_id,
+        role_arn: Some(role_arn),
+        session_token: credentials.session_token,
+        expiration: expiration_timestamp,
+    })
+}
+
+fn exceeds_max_session_duration<E: ProvideErrorMetadata, R>(error: &SdkError<E, R>) -> bool {
+    match error.as_service_error() {
+        Some(e) => {
+            e.code() == Some("ValidationError")
+                && e.message()
+                    .is_some_and(|message| message.contains("MaxSessionDuration"))
+        }
+        None => false,
+    }
 }
 
 #[derive(Clone)]
@@ -79,3 +278,84 @@ struct StsFuture {
     role_arn: String,
     request: Result<AssumeRoleWithSamlOutput>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_assume_role_chained() {
+        let server = httpmock::MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/")
+                .body_contains("Action=AssumeRole")
+                .body_contains("RoleArn=arn%3Aaws%3Aiam%3A%3A000333111000%3Arole%2FSpoke")
+                .body_contains("ExternalId=TheExternalId")
+                .body_contains("RoleSessionName=c9s")
+                .body_contains("Tags.member.1.Key=team")
+                .body_contains("DurationSeconds=3600");
+            then.status(200).header("content-type", "text/xml").body(
+                r#"<AssumeRoleResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <AssumeRoleResult>
+    <Credentials>
+      <AccessKeyId>SpokeAccessKeyId</AccessKeyId>
+      <SecretAccessKey>SpokeSecretAccessKey</SecretAccessKey>
+      <SessionToken>SpokeSessionToken</SessionToken>
+      <Expiration>2030-01-01T00:00:00Z</Expiration>
+    </Credentials>
+    <AssumedRoleUser>
+      <AssumedRoleId>AROA:c9s</AssumedRoleId>
+      <Arn>arn:aws:sts::000333111000:assumed-role/Spoke/c9s</Arn>
+    </AssumedRoleUser>
+  </AssumeRoleResult>
+  <ResponseMetadata><RequestId>TheRequestId</RequestId></ResponseMetadata>
+</AssumeRoleResponse>"#,
+            );
+        });
+
+        let source = Credential {
+            secret_access_key: String::from("HubSecretAccessKey"),
+            access_key_id: String::from("HubAccessKeyId"),
+            session_token: String::from("HubSessionToken"),
+            role_arn: Some(String::from("arn:aws:iam::000222111000:role/Hub")),
+            expiration: String::from("2030-01-01T00:00:00Z"),
+        };
+        let hop = RoleHop::new(
+            String::from("arn:aws:iam::000333111000:role/Spoke"),
+            Some(String::from("TheExternalId")),
+            None,
+            BTreeMap::from([(String::from("team"), String::from("platform"))]),
+        )
+        .unwrap();
+        let options = StsOptions::new(Some(String::from("eu-west-1")), Some(server.url("/")))
+            .with_duration(Some(43200));
+
+        let credential = StsClient::new(options)
+            .unwrap()
+            .assume_role_chained(&source, &hop)
+            .await
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(credential.access_key_id(), "SpokeAccessKeyId");
+        assert_eq!(
+            credential.role_arn(),
+            Some(String::from("arn:aws:iam::000333111000:role/Spoke"))
+        );
+        assert_eq!(credential.expiration(), "2030-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_duration_is_capped_by_the_assertion() {
+        let options = StsOptions::default();
+        assert_eq!(options.duration(None), 3600);
+        assert_eq!(options.duration(Some(43200)), 3600);
+        assert_eq!(options.duration(Some(900)), 900);
+
+        let options = StsOptions::default().with_duration(Some(28800));
+        assert_eq!(options.duration(None), 28800);
+        assert_eq!(options.duration(Some(43200)), 28800);
+        assert_eq!(options.duration(Some(7200)), 7200);
+    }
+}
@@ -0,0 +1,385 @@
+use crate::credentials::AwsCredentialArgs;
+use anyhow::{anyhow, Result};
+use c9s::aws::Credential;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use std::collections::HashMap;
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::time::{Duration, Instant};
+
+/// How often the agent looks for credentials to renew.
+#[cfg(unix)]
+const RENEWAL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Credentials which nobody requested for this long are no longer renewed.
+#[cfg(unix)]
+const RENEWAL_IDLE_TIMEOUT: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// How long a client waits for the agent's answer, which includes the time to answer MFA.
+#[cfg(unix)]
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Parser)]
+/// Runs an agent which keeps Okta sessions and credentials in memory and hands them to other c9s
+/// commands over a Unix socket.
+///
+/// Requests are handled one after another, so that only one login and MFA prompt happens at a
+/// time. Run it in a terminal to answer password prompts.
+///
+/// Credentials which were requested within the last 12 hours are renewed in the background
+/// before they expire, as long as the Okta session is valid.
+pub struct Agent {}
+
+/// A request to the agent, sent as a single line of JSON.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Request {
+    Credentials { args: Box<AwsCredentialArgs> },
+}
+
+/// The agent's answer to a request, sent as a single line of JSON.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Response {
+    Credentials { credentials: Vec<Credential> },
+    Error { message: String },
+}
+
+impl Agent {
+    pub async fn run(&self) -> Result<()> {
+        serve(socket_path()?).await
+    }
+}
+
+/// The socket of the agent, `C9S_AGENT_SOCKET` or `c9s/agent.sock` in the user's runtime
+/// directory.
+fn socket_path() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os("C9S_AGENT_SOCKET") {
+        return Ok(PathBuf::from(path));
+    }
+
+    let dir = dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .ok_or_else(|| anyhow!("could not find a directory for the agent's socket"))?;
+
+    Ok(dir.join("c9s").join("agent.sock"))
+}
+
+#[cfg(unix)]
+async fn serve(path: PathBuf) -> Result<()> {
+    use crate::utils;
+    use std::fs;
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    use tokio::net::{UnixListener, UnixStream};
+
+    // SAFETY: getuid has no memory safety requirements and can't fail.
+    let uid = unsafe { libc::getuid() };
+
+    if let Some(dir) = path.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        let metadata = fs::metadata(dir)?;
+        if metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+            return Err(anyhow!(
+                "{dir:?} has to be owned by you and only accessible by you (mode 0700)"
+            ));
+        }
+    }
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            return Err(anyhow!("an agent is already listening on {path:?}"));
+        }
+        fs::remove_file(&path)?;
+    }
+
+    // the socket is created without permissions for others, instead of restricting them after
+    // binding
+    // SAFETY: umask has no memory safety requirements, the old mask is restored right after.
+    let mask = unsafe { libc::umask(0o077) };
+    let listener = UnixListener::bind(&path);
+    unsafe {
+        libc::umask(mask);
+    }
+    let listener = listener?;
+    utils::keep_secrets_in_memory();
+    eprintln!("c9s agent listening on {path:?}");
+
+    // the arguments of requested credentials by their JSON, and when they were last requested
+    let mut renewals: HashMap<String, (AwsCredentialArgs, Instant)> = HashMap::new();
+    let mut renewal = tokio::time::interval(RENEWAL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                if !is_peer(&stream, uid) {
+                    eprintln!("rejected a connection of another user");
+                    continue;
+                }
+                match handle(stream).await {
+                    Ok(Some(args)) => {
+                        let key = serde_json::to_string(&args)?;
+                        renewals.insert(key, (args, Instant::now()));
+                    }
+                    Ok(None) => {}
+                    Err(e) => eprintln!("could not answer request: {e:#}"),
+                }
+            }
+            _ = renewal.tick() => renew(&mut renewals).await,
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+/// Whether the other end of the stream runs as the user `uid`.
+#[cfg(unix)]
+fn is_peer(stream: &tokio::net::UnixStream, uid: libc::uid_t) -> bool {
+    stream.peer_cred().is_ok_and(|peer| peer.uid() == uid)
+}
+
+/// Renews the credentials which are about to expire, forgetting the ones which weren't requested
+/// for a while or can't be renewed anymore.
+#[cfg(unix)]
+async fn renew(renewals: &mut HashMap<String, (AwsCredentialArgs, Instant)>) {
+    use c9s::settings::AppConfig;
+
+    renewals.retain(|_, (_, requested)| requested.elapsed() < RENEWAL_IDLE_TIMEOUT);
+    if renewals.is_empty() {
+        return;
+    }
+    let settings = match AppConfig::read_config() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("could not read the settings to renew credentials: {e}");
+            return;
+        }
+    };
+
+    let mut failed = vec![];
+    for (key, (args, _)) in renewals.iter() {
+        // cached credentials outside of the refresh window are returned as they are
+        if let Err(e) = args.renew(&settings).await {
+            eprintln!("stopped renewing credentials: {e:#}");
+            failed.push(key.clone());
+        }
+    }
+    for key in failed {
+        renewals.remove(&key);
+    }
+}
+
+#[cfg(not(unix))]
+async fn serve(_path: PathBuf) -> Result<()> {
+    Err(anyhow!("the agent is only available on Unix"))
+}
+
+/// Answers a single request. Clients have a few seconds to send it, so that a stuck client
+/// doesn't block the agent.
+///
+/// Returns the arguments of the credentials handed out, to renew them later.
+#[cfg(unix)]
+async fn handle(stream: tokio::net::UnixStream) -> Result<Option<AwsCredentialArgs>> {
+    use c9s::settings::AppConfig;
+
+    answer(stream, |args| async move {
+        let settings = AppConfig::read_config()?;
+        args.fetch_local(&settings).await
+    })
+    .await
+}
+
+/// Reads a request from the stream and answers it with the credentials `fetch` returns.
+#[cfg(unix)]
+async fn answer<F, Fut>(
+    stream: tokio::net::UnixStream,
+    fetch: F,
+) -> Result<Option<AwsCredentialArgs>>
+where
+    F: FnOnce(AwsCredentialArgs) -> Fut,
+    Fut: std::future::Future<Output = Result<Vec<Credential>>>,
+{
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        BufReader::new(reader).read_line(&mut line),
+    )
+    .await
+    .map_err(|_| anyhow!("timed out waiting for the request"))??;
+
+    let mut renewal = None;
+    let response = match serde_json::from_str(line.as_str()) {
+        Ok(Request::Credentials { args }) => match fetch((*args).clone()).await {
+            Ok(credentials) => {
+                renewal = Some(*args);
+                Response::Credentials { credentials }
+            }
+            Err(e) => Response::Error {
+                message: format!("{e:#}"),
+            },
+        },
+        Err(e) => Response::Error {
+            message: format!("invalid request: {e}"),
+        },
+    };
+
+    let mut json = serde_json::to_string(&response)?;
+    json.push('\n');
+    writer.write_all(json.as_bytes()).await?;
+
+    Ok(renewal)
+}
+
+/// Asks a running agent for the credentials, `None` when no agent is running.
+#[cfg(unix)]
+pub async fn request_credentials(args: &AwsCredentialArgs) -> Result<Option<Vec<Credential>>> {
+    use tokio::net::UnixStream;
+
+    let stream = match UnixStream::connect(socket_path()?).await {
+        Ok(stream) => stream,
+        Err(_) => return Ok(None),
+    };
+
+    exchange(stream, args).await.map(Some)
+}
+
+/// Sends the request for the credentials to the agent and reads its answer.
+#[cfg(unix)]
+async fn exchange(
+    stream: tokio::net::UnixStream,
+    args: &AwsCredentialArgs,
+) -> Result<Vec<Credential>> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (reader, mut writer) = stream.into_split();
+
+    let request = Request::Credentials {
+        args: Box::new(args.clone()),
+    };
+    let mut json = serde_json::to_string(&request)?;
+    json.push('\n');
+    writer.write_all(json.as_bytes()).await?;
+
+    let mut line = String::new();
+    tokio::time::timeout(
+        RESPONSE_TIMEOUT,
+        BufReader::new(reader).read_line(&mut line),
+    )
+    .await
+    .map_err(|_| anyhow!("timed out waiting for the agent"))??;
+
+    match serde_json::from_str(line.as_str())
+        .map_err(|e| anyhow!("invalid response from the agent: {e}"))?
+    {
+        Response::Credentials { credentials } => Ok(credentials),
+        Response::Error { message } => Err(anyhow!("agent: {message}")),
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn request_credentials(_args: &AwsCredentialArgs) -> Result<Option<Vec<Credential>>> {
+    Ok(None)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixStream;
+
+    const ROLE_ARN: &str = "arn:aws:iam::000222111000:role/Admin";
+
+    fn args() -> AwsCredentialArgs {
+        AwsCredentialArgs::try_parse_from(["creds", "--role-arn", ROLE_ARN]).unwrap()
+    }
+
+    fn credential() -> Credential {
+        serde_json::from_value(serde_json::json!({
+            "secretAccessKey": "TheSecretAccessKey",
+            "accessKeyId": "TheAccessKeyId",
+            "sessionToken": "TheSessionToken",
+            "roleArn": ROLE_ARN,
+            "expiration": "2030-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_hand_out_credentials() {
+        let (client, agent) = UnixStream::pair().unwrap();
+        let args = args();
+        let requested = serde_json::to_string(&args).unwrap();
+
+        let (renewal, credentials) = tokio::join!(
+            answer(agent, |received| async move {
+                assert_eq!(serde_json::to_string(&received).unwrap(), requested);
+                Ok(vec![credential()])
+            }),
+            exchange(client, &args),
+        );
+        let credentials = credentials.unwrap();
+
+        assert!(renewal.unwrap().is_some());
+        assert_eq!(credentials.len(), 1);
+        assert_eq!(credentials[0].session_token(), "TheSessionToken");
+        assert_eq!(credentials[0].role_arn(), Some(String::from(ROLE_ARN)));
+    }
+
+    #[tokio::test]
+    async fn test_forward_errors() {
+        let (client, agent) = UnixStream::pair().unwrap();
+        let args = args();
+
+        let (renewal, credentials) = tokio::join!(
+            answer(agent, |_| async { Err(anyhow!("no role matched")) }),
+            exchange(client, &args),
+        );
+
+        assert!(renewal.unwrap().is_none());
+        assert_eq!(
+            credentials.err().unwrap().to_string(),
+            "agent: no role matched"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reject_invalid_requests() {
+        let (client, agent) = UnixStream::pair().unwrap();
+        let (reader, mut writer) = client.into_split();
+
+        writer
+            .write_all(b"{\"type\":\"shutdown\"}\n")
+            .await
+            .unwrap();
+        let renewal = answer(agent, |_| async { Ok(vec![credential()]) })
+            .await
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).await.unwrap();
+
+        assert!(renewal.is_none());
+        assert!(matches!(
+            serde_json::from_str(line.as_str()).unwrap(),
+            Response::Error { message } if message.starts_with("invalid request")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_only_accept_the_same_user() {
+        let (stream, _) = UnixStream::pair().unwrap();
+        // SAFETY: getuid has no memory safety requirements and can't fail.
+        let uid = unsafe { libc::getuid() };
+
+        assert!(is_peer(&stream, uid));
+        assert!(!is_peer(&stream, uid + 1));
+    }
+}
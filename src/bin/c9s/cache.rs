@@ -0,0 +1,229 @@
+use crate::utils;
+use anyhow::{anyhow, Result};
+use c9s::aws::role_filter::RolePattern;
+use c9s::cache::index::{CacheEntry, CacheIndex, EntryKind};
+use c9s::settings::AppConfig;
+use clap::{ArgGroup, Parser, ValueEnum};
+use serde::Serialize;
+
+#[derive(Parser)]
+/// Lists and clears the sessions, tokens and credentials c9s has cached.
+pub struct Cache {
+    #[clap(subcommand)]
+    sub_command: CacheSubCommands,
+}
+
+#[derive(Parser)]
+enum CacheSubCommands {
+    List(CacheList),
+    Clear(CacheClear),
+}
+
+#[derive(Parser)]
+/// Lists the cached secrets with their expiration, without the secrets themselves.
+struct CacheList {
+    #[clap(long, value_enum, default_value_t)]
+    output: CacheOutput,
+}
+
+#[derive(Parser)]
+#[clap(group(ArgGroup::new("entries").required(true).args(["role", "expired", "all"])))]
+/// Removes cached credentials, or everything c9s has cached except for saved passwords.
+struct CacheClear {
+    /// The credentials of the roles matching the pattern, a role ARN or a glob like
+    /// `*:role/Admin`. Regular expressions are prefixed with `re:`.
+    #[clap(long)]
+    role: Option<String>,
+    /// All expired sessions, tokens and credentials.
+    #[clap(long)]
+    expired: bool,
+    /// All sessions, tokens and credentials.
+    #[clap(long)]
+    all: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+enum CacheOutput {
+    #[default]
+    Table,
+    Json,
+}
+
+#[derive(Parser)]
+/// Manages the passwords saved in the keyring.
+pub struct Password {
+    #[clap(subcommand)]
+    sub_command: PasswordSubCommands,
+}
+
+#[derive(Parser)]
+enum PasswordSubCommands {
+    Forget(PasswordForget),
+}
+
+#[derive(Parser)]
+/// Removes the saved password of an Okta host from the keyring.
+struct PasswordForget {
+    #[clap(long)]
+    app_url: String,
+    /// The user whose password is removed, by default every user with a saved password for the
+    /// host.
+    #[clap(short, long)]
+    username: Option<String>,
+}
+
+/// A cached secret as it is printed.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EntryRow {
+    kind: &'static str,
+    name: String,
+    host: Option<String>,
+    expiration: Option<String>,
+    remaining: Option<String>,
+}
+
+impl Cache {
+    pub fn run(&self, settings: AppConfig) -> Result<()> {
+        match &self.sub_command {
+            CacheSubCommands::List(val) => val.run(&settings),
+            CacheSubCommands::Clear(val) => val.run(&settings),
+        }
+    }
+}
+
+impl CacheList {
+    fn run(&self, settings: &AppConfig) -> Result<()> {
+        let mut entries = CacheIndex::from_settings(settings)?.entries()?;
+        entries.sort_by_key(|entry| (entry.kind().name(), entry.host(), name(entry)));
+        let rows: Vec<EntryRow> = entries.iter().map(EntryRow::new).collect();
+
+        match self.output {
+            CacheOutput::Table => {
+                let values: Vec<Vec<String>> =
+                    rows.iter().map(|row| row.values().to_vec()).collect();
+                utils::print_table(&EntryRow::HEADERS, &values)
+            }
+            CacheOutput::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+        }
+
+        Ok(())
+    }
+}
+
+impl CacheClear {
+    fn run(&self, settings: &AppConfig) -> Result<()> {
+        let pattern = self.role.as_deref().map(RolePattern::new).transpose()?;
+        let entries = CacheIndex::from_settings(settings)?.entries()?;
+
+        let cleared: Vec<&CacheEntry> = entries
+            .iter()
+            .filter(|entry| entry.kind() != EntryKind::Password)
+            .filter(|entry| match &pattern {
+                Some(pattern) => {
+                    entry.kind() == EntryKind::Credential
+                        && entry
+                            .role_arn()
+                            .is_some_and(|role_arn| pattern.is_match(&role_arn))
+                }
+                None => self.all || entry.is_expired(),
+            })
+            .collect();
+
+        for entry in &cleared {
+            utils::delete_secret(entry, settings)?;
+        }
+        eprintln!("cleared {} cached entries", cleared.len());
+
+        Ok(())
+    }
+}
+
+impl Password {
+    pub fn run(&self, settings: AppConfig) -> Result<()> {
+        match &self.sub_command {
+            PasswordSubCommands::Forget(val) => val.run(&settings),
+        }
+    }
+}
+
+impl PasswordForget {
+    fn run(&self, settings: &AppConfig) -> Result<()> {
+        let usernames = match &self.username {
+            Some(username) => vec![username.clone()],
+            None => saved_password_users(&self.app_url, settings)?,
+        };
+        if usernames.is_empty() {
+            return Err(anyhow!(
+                "no saved password found for {}, please supply a username",
+                self.app_url
+            ));
+        }
+
+        for username in usernames {
+            utils::forget_password(&self.app_url, &username, settings)?;
+            eprintln!("forgot the password of {username}");
+        }
+
+        Ok(())
+    }
+}
+
+/// The users with a saved password for the host, or else the user configured for it.
+fn saved_password_users(app_url: &str, settings: &AppConfig) -> Result<Vec<String>> {
+    let service = utils::password_service(app_url)?;
+    let users: Vec<String> = CacheIndex::from_settings(settings)?
+        .entries()?
+        .iter()
+        .filter(|entry| entry.kind() == EntryKind::Password && entry.service() == service)
+        .map(CacheEntry::user)
+        .collect();
+    if !users.is_empty() {
+        return Ok(users);
+    }
+
+    Ok(utils::configured_username(app_url, settings)
+        .into_iter()
+        .collect())
+}
+
+/// What the secret belongs to: the role of a credential, or the user of anything else.
+fn name(entry: &CacheEntry) -> String {
+    match entry.kind() {
+        EntryKind::Credential => entry.role_arn().unwrap_or_else(|| entry.user()),
+        _ => entry.user(),
+    }
+}
+
+impl EntryRow {
+    fn new(entry: &CacheEntry) -> Self {
+        let remaining = entry.remaining().map(|remaining| {
+            if remaining.is_negative() {
+                String::from("expired")
+            } else {
+                let minutes = remaining.whole_minutes();
+                format!("{}h{:02}m", minutes / 60, minutes % 60)
+            }
+        });
+
+        Self {
+            kind: entry.kind().name(),
+            name: name(entry),
+            host: entry.host(),
+            expiration: entry.expiration(),
+            remaining,
+        }
+    }
+
+    const HEADERS: [&'static str; 5] = ["KIND", "NAME", "HOST", "EXPIRATION", "REMAINING"];
+
+    fn values(&self) -> [String; 5] {
+        [
+            self.kind.to_string(),
+            self.name.clone(),
+            self.host.clone().unwrap_or_default(),
+            self.expiration.clone().unwrap_or_default(),
+            self.remaining.clone().unwrap_or_default(),
+        ]
+    }
+}
@@ -1,7 +1,15 @@
-use crate::utils::true_or_false;
+use crate::credentials::AwsHostArgs;
+use crate::utils::{key_value, true_or_false};
+use anyhow::anyhow;
 use anyhow::Result;
-use c9s::settings::{AppConfig, AwsDefaults, AwsHost, AwsSsoHost, SsoProvider};
-use clap::Parser;
+use c9s::aws::shared_files::{self, IniDocument, SharedFile};
+use c9s::aws::sts::RoleHop;
+use c9s::settings::{
+    AppConfig, AwsDefaults, AwsEndpoints, AwsHost, AwsSsoHost, CacheBackend, OktaAuthenticator,
+    OutputOptions, Profile, SsoProvider,
+};
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 pub struct Config {
@@ -13,6 +21,7 @@ pub struct Config {
 enum ConfigSubCommand {
     Aws(ConfigAws),
     Global(ConfigGlobal),
+    Profile(ConfigProfile),
 }
 
 #[derive(Parser)]
@@ -23,9 +32,11 @@ struct ConfigAws {
 
 #[derive(Parser)]
 enum ConfigAwsSubCommand {
+    Alias(ConfigAwsAlias),
     Defaults(ConfigAwsDefaults),
-    OktaAws(ConfigAwsOktaAws),
-    OktaAwsSso(ConfigAwsOktaAwsSso),
+    OktaAws(Box<ConfigAwsOktaAws>),
+    OktaAwsSso(Box<ConfigAwsOktaAwsSso>),
+    SyncProfiles(Box<ConfigAwsSyncProfiles>),
 }
 
 #[derive(Parser)]
@@ -38,6 +49,16 @@ struct ConfigAwsOktaAws {
     mfa: Option<String>,
     #[clap(long)]
     mfa_provider: Option<String>,
+    /// The session duration in seconds, between 900 and 43200, capped by the SessionDuration
+    /// Okta allows.
+    #[clap(long, value_parser = clap::value_parser!(i32).range(900..=43200))]
+    session_duration: Option<i32>,
+    /// How to authenticate against Okta, Identity Engine is used when `auto` finds the Classic
+    /// API disabled.
+    #[clap(long, value_enum)]
+    authenticator: Option<OktaAuthenticator>,
+    #[clap(flatten)]
+    endpoints: AwsEndpoints,
 }
 
 #[derive(Parser)]
@@ -52,6 +73,163 @@ struct ConfigAwsOktaAwsSso {
     mfa: Option<String>,
     #[clap(long)]
     mfa_provider: Option<String>,
+    /// How to authenticate against Okta, Identity Engine is used when `auto` finds the Classic
+    /// API disabled.
+    #[clap(long, value_enum)]
+    authenticator: Option<OktaAuthenticator>,
+    #[clap(flatten)]
+    endpoints: AwsEndpoints,
+}
+
+#[derive(Parser)]
+/// Manages friendly names for AWS accounts and roles, usable with `--role`.
+///
+/// Account names from AWS SSO are added automatically when roles are listed.
+struct ConfigAwsAlias {
+    #[clap(subcommand)]
+    sub_command: ConfigAwsAliasSubCommand,
+}
+
+#[derive(Parser)]
+enum ConfigAwsAliasSubCommand {
+    Account(ConfigAwsAliasAccount),
+    Role(ConfigAwsAliasRole),
+    List(ConfigAwsAliasList),
+    Remove(ConfigAwsAliasRemove),
+}
+
+#[derive(Parser)]
+/// Sets the alias of an account, roles can then be given as `alias/role-name`.
+struct ConfigAwsAliasAccount {
+    account_id: String,
+    alias: String,
+}
+
+#[derive(Parser)]
+/// Sets a short name for a role.
+struct ConfigAwsAliasRole {
+    name: String,
+    role_arn: String,
+}
+
+#[derive(Parser)]
+/// Lists all aliases.
+struct ConfigAwsAliasList {}
+
+#[derive(Parser)]
+/// Removes a role's short name, or an account alias by its name or account id.
+struct ConfigAwsAliasRemove {
+    name: String,
+}
+
+#[derive(Parser)]
+/// Manages named profiles for `c9s creds aws --profile`.
+struct ConfigProfile {
+    #[clap(subcommand)]
+    sub_command: ConfigProfileSubCommand,
+}
+
+#[derive(Parser)]
+enum ConfigProfileSubCommand {
+    Add(Box<ConfigProfileAdd>),
+    Chain(ConfigProfileChain),
+    List(ConfigProfileList),
+    Remove(ConfigProfileRemove),
+}
+
+#[derive(Parser)]
+/// Adds a profile, or replaces an existing one with the same name.
+struct ConfigProfileAdd {
+    /// The name of the profile.
+    name: String,
+    #[clap(long, value_enum)]
+    provider: Option<SsoProvider>,
+    #[clap(long)]
+    app_url: Option<String>,
+    #[clap(short, long)]
+    username: Option<String>,
+    #[clap(short, long)]
+    role_arn: Option<String>,
+    #[clap(long)]
+    region: Option<String>,
+    #[clap(short, long)]
+    mfa: Option<String>,
+    #[clap(long)]
+    mfa_provider: Option<String>,
+    /// The session duration in seconds, between 900 and 43200.
+    #[clap(long, value_parser = clap::value_parser!(i32).range(900..=43200))]
+    session_duration: Option<i32>,
+    #[clap(long, value_enum)]
+    output: Option<OutputOptions>,
+    #[clap(flatten)]
+    endpoints: AwsEndpoints,
+}
+
+#[derive(Parser)]
+/// Manages the roles a profile assumes after its role, with the credentials of the previous one.
+struct ConfigProfileChain {
+    #[clap(subcommand)]
+    sub_command: ConfigProfileChainSubCommand,
+}
+
+#[derive(Parser)]
+enum ConfigProfileChainSubCommand {
+    Add(ConfigProfileChainAdd),
+    Clear(ConfigProfileChainClear),
+}
+
+#[derive(Parser)]
+/// Appends a role to the end of the profile's chain.
+struct ConfigProfileChainAdd {
+    /// The name of the profile.
+    name: String,
+    #[clap(required = true, short, long)]
+    role_arn: String,
+    #[clap(long)]
+    external_id: Option<String>,
+    /// The role session name, defaults to `c9s`.
+    #[clap(long)]
+    session_name: Option<String>,
+    /// A session tag as `key=value`, can be given several times.
+    #[clap(long = "tag", value_parser = key_value)]
+    tags: Vec<(String, String)>,
+}
+
+#[derive(Parser)]
+/// Removes all roles from the profile's chain.
+struct ConfigProfileChainClear {
+    /// The name of the profile.
+    name: String,
+}
+
+#[derive(Parser)]
+/// Lists all profiles.
+struct ConfigProfileList {}
+
+#[derive(Parser)]
+/// Removes a profile.
+struct ConfigProfileRemove {
+    /// The name of the profile.
+    name: String,
+}
+
+#[derive(Parser)]
+/// Writes a profile for every role of a host into the AWS CLI config file.
+///
+/// The profiles are kept in a block managed by c9s, profiles of roles which disappeared are
+/// removed from it on the next sync.
+struct ConfigAwsSyncProfiles {
+    #[clap(flatten)]
+    host: AwsHostArgs,
+    /// The profile name, `{account_id}` and `{role_name}` are replaced with the values of each role.
+    #[clap(long, default_value = "{account_id}-{role_name}")]
+    profile_template: String,
+    /// The region written into every profile.
+    #[clap(long)]
+    default_region: Option<String>,
+    /// Only print the changes, without writing the config file.
+    #[clap(long)]
+    dry_run: bool,
 }
 
 #[derive(Parser)]
@@ -63,6 +241,8 @@ struct ConfigGlobal {
 #[derive(Parser)]
 enum ConfigGlobalSubCommand {
     UseKeyring(ConfigGlobalUseKeyRing),
+    RefreshWindow(ConfigGlobalRefreshWindow),
+    Cache(ConfigGlobalCache),
 }
 
 #[derive(Parser)]
@@ -73,6 +253,25 @@ struct ConfigGlobalUseKeyRing {
     enabled: bool,
 }
 
+#[derive(Parser)]
+/// How long before their expiration credentials are renewed.
+struct ConfigGlobalRefreshWindow {
+    /// The window in seconds, 600 by default.
+    #[clap(long, value_parser = clap::value_parser!(i64).range(0..))]
+    seconds: i64,
+}
+
+#[derive(Parser)]
+/// Where sessions and credentials are cached.
+struct ConfigGlobalCache {
+    #[clap(long, value_enum)]
+    backend: CacheBackend,
+    /// The key of the encrypted file cache, `cache.key` in the config directory by default.
+    /// Ignored when `C9S_CACHE_PASSPHRASE` is set.
+    #[clap(long)]
+    key_file: Option<PathBuf>,
+}
+
 #[derive(Parser)]
 /// Sets the default AWS provider
 struct ConfigAwsDefaults {
@@ -81,15 +280,33 @@ struct ConfigAwsDefaults {
 }
 
 impl Config {
-    pub fn run(&self, settings: &mut AppConfig) -> Result<()> {
+    pub async fn run(&self, settings: &mut AppConfig) -> Result<()> {
         match &self.sub_command {
             ConfigSubCommand::Aws(val) => match &val.sub_command {
+                ConfigAwsSubCommand::Alias(val) => match &val.sub_command {
+                    ConfigAwsAliasSubCommand::Account(val) => val.run(settings),
+                    ConfigAwsAliasSubCommand::Role(val) => val.run(settings),
+                    ConfigAwsAliasSubCommand::List(val) => val.run(settings),
+                    ConfigAwsAliasSubCommand::Remove(val) => val.run(settings),
+                },
                 ConfigAwsSubCommand::Defaults(val) => val.run(settings),
                 ConfigAwsSubCommand::OktaAws(val) => val.run(settings),
                 ConfigAwsSubCommand::OktaAwsSso(val) => val.run(settings),
+                ConfigAwsSubCommand::SyncProfiles(val) => val.run(settings).await,
             },
             ConfigSubCommand::Global(val) => match &val.sub_command {
                 ConfigGlobalSubCommand::UseKeyring(val) => val.run(settings),
+                ConfigGlobalSubCommand::RefreshWindow(val) => val.run(settings),
+                ConfigGlobalSubCommand::Cache(val) => val.run(settings),
+            },
+            ConfigSubCommand::Profile(val) => match &val.sub_command {
+                ConfigProfileSubCommand::Add(val) => val.run(settings),
+                ConfigProfileSubCommand::Chain(val) => match &val.sub_command {
+                    ConfigProfileChainSubCommand::Add(val) => val.run(settings),
+                    ConfigProfileChainSubCommand::Clear(val) => val.run(settings),
+                },
+                ConfigProfileSubCommand::List(val) => val.run(settings),
+                ConfigProfileSubCommand::Remove(val) => val.run(settings),
             },
         }
     }
@@ -102,6 +319,9 @@ impl ConfigAwsOktaAws {
             self.username.clone(),
             self.mfa.clone(),
             self.mfa_provider.clone(),
+            self.session_duration,
+            self.authenticator,
+            self.endpoints.clone(),
         )?;
         settings.add_aws_host(host);
         settings.write_config()?;
@@ -118,6 +338,8 @@ impl ConfigAwsOktaAwsSso {
             self.region.clone(),
             self.mfa.clone(),
             self.mfa_provider.clone(),
+            self.authenticator,
+            self.endpoints.clone(),
         )?;
         settings.add_aws_sso_host(host);
         settings.write_config()?;
@@ -126,6 +348,105 @@ impl ConfigAwsOktaAwsSso {
     }
 }
 
+impl ConfigAwsSyncProfiles {
+    async fn run(&self, settings: &AppConfig) -> Result<()> {
+        let host = self.host.with_profile(settings)?;
+        let (aws_settings, roles) = host.roles(settings).await?;
+
+        let file = SharedFile::config()?;
+        let document = file.read()?;
+        let block_id = aws_settings.app_url.clone();
+        let unmanaged = document.unmanaged_section_names(&block_id);
+
+        let mut lines = vec![];
+        for role in roles {
+            let profile_name =
+                shared_files::profile_name(&self.profile_template, &role.role_arn())?;
+            let section = format!("profile {profile_name}");
+            if unmanaged.contains(&section) {
+                eprintln!("skipping profile {profile_name}, it is already defined outside of c9s");
+                continue;
+            }
+
+            let mut command = format!(
+                "c9s creds aws --sso-provider {} --app-url {} --role-arn {} --output aws-profile",
+                value_name(aws_settings.provider).unwrap_or_default(),
+                aws_settings.app_url,
+                role.role_arn()
+            );
+            if let (SsoProvider::OktaAwsSso, Some(region)) =
+                (aws_settings.provider, &aws_settings.region)
+            {
+                command.push_str(format!(" --region {region}").as_str());
+            }
+            let endpoints = &aws_settings.endpoints;
+            if let Some(partition) = endpoints.partition().and_then(value_name) {
+                command.push_str(format!(" --partition {partition}").as_str());
+            }
+            if let Some(sts_region) = endpoints.sts_region() {
+                command.push_str(format!(" --sts-region {sts_region}").as_str());
+            }
+            if let Some(sts_endpoint) = endpoints.sts_endpoint() {
+                command.push_str(format!(" --sts-endpoint {sts_endpoint}").as_str());
+            }
+            if let Some(sso_portal_url) = endpoints.sso_portal_url() {
+                command.push_str(format!(" --sso-portal-url {sso_portal_url}").as_str());
+            }
+
+            lines.push(format!("[{section}]"));
+            if let Some(region) = &self.default_region {
+                lines.push(format!("region = {region}"));
+            }
+            lines.push(format!(
+                "credential_process = sh -c \"{command} 2> /dev/tty\""
+            ));
+            lines.push(String::new());
+        }
+
+        let old_block = IniDocument::parse(
+            document
+                .managed_block(&block_id)
+                .unwrap_or_default()
+                .join("\n")
+                .as_str(),
+        );
+        let new_block = IniDocument::parse(lines.join("\n").as_str());
+        print_profile_changes(&old_block, &new_block);
+
+        if self.dry_run {
+            return Ok(());
+        }
+
+        file.update(|document| {
+            document.set_managed_block(&block_id, lines);
+            Ok(())
+        })?;
+        eprintln!("updated {:?}", file.path());
+
+        Ok(())
+    }
+}
+
+/// Prints which profiles are added (+), changed (~) and removed (-).
+fn print_profile_changes(old: &IniDocument, new: &IniDocument) {
+    let old_sections = old.section_names();
+    let new_sections = new.section_names();
+
+    for section in &new_sections {
+        if !old_sections.contains(section) {
+            println!("+ [{section}]");
+        } else if old.section(section) != new.section(section) {
+            println!("~ [{section}]");
+        }
+    }
+
+    for section in &old_sections {
+        if !new_sections.contains(section) {
+            println!("- [{section}]");
+        }
+    }
+}
+
 impl ConfigGlobalUseKeyRing {
     fn run(&self, settings: &mut AppConfig) -> Result<()> {
         settings.set_use_keyring(self.enabled);
@@ -135,6 +456,24 @@ impl ConfigGlobalUseKeyRing {
     }
 }
 
+impl ConfigGlobalRefreshWindow {
+    fn run(&self, settings: &mut AppConfig) -> Result<()> {
+        settings.set_refresh_window(self.seconds);
+        settings.write_config()?;
+
+        Ok(())
+    }
+}
+
+impl ConfigGlobalCache {
+    fn run(&self, settings: &mut AppConfig) -> Result<()> {
+        settings.set_cache_backend(self.backend, self.key_file.clone());
+        settings.write_config()?;
+
+        Ok(())
+    }
+}
+
 impl ConfigAwsDefaults {
     fn run(&self, settings: &mut AppConfig) -> Result<()> {
         let defaults = AwsDefaults::new(self.sso_provider);
@@ -145,3 +484,162 @@ impl ConfigAwsDefaults {
         Ok(())
     }
 }
+
+impl ConfigAwsAliasAccount {
+    fn run(&self, settings: &mut AppConfig) -> Result<()> {
+        let mut aliases = settings.aws_aliases();
+        aliases.set_account(self.account_id.clone(), self.alias.clone())?;
+        settings.set_aws_aliases(aliases);
+        settings.write_config()?;
+
+        Ok(())
+    }
+}
+
+impl ConfigAwsAliasRole {
+    fn run(&self, settings: &mut AppConfig) -> Result<()> {
+        let mut aliases = settings.aws_aliases();
+        aliases.set_role(self.name.clone(), self.role_arn.clone())?;
+        settings.set_aws_aliases(aliases);
+        settings.write_config()?;
+
+        Ok(())
+    }
+}
+
+impl ConfigAwsAliasList {
+    fn run(&self, settings: &mut AppConfig) -> Result<()> {
+        let aliases = settings.aws_aliases();
+        for (account_id, alias) in aliases.accounts() {
+            println!("{alias}: {account_id}");
+        }
+        for (name, role_arn) in aliases.roles() {
+            println!("{name}: {role_arn}");
+        }
+
+        Ok(())
+    }
+}
+
+impl ConfigAwsAliasRemove {
+    fn run(&self, settings: &mut AppConfig) -> Result<()> {
+        let mut aliases = settings.aws_aliases();
+        if !aliases.remove(self.name.as_str()) {
+            return Err(anyhow!("could not find alias {}", self.name));
+        }
+        settings.set_aws_aliases(aliases);
+        settings.write_config()?;
+
+        Ok(())
+    }
+}
+
+impl ConfigProfileAdd {
+    fn run(&self, settings: &mut AppConfig) -> Result<()> {
+        let profile = Profile::new(
+            self.provider,
+            self.app_url.clone(),
+            self.username.clone(),
+            self.role_arn.clone(),
+            self.region.clone(),
+            self.mfa.clone(),
+            self.mfa_provider.clone(),
+            self.session_duration,
+            self.output,
+            self.endpoints.clone(),
+        )?;
+        // a replaced profile keeps its chain, which is managed by `config profile chain`
+        let chain = settings
+            .find_profile(self.name.as_str())
+            .map(|profile| profile.chain())
+            .unwrap_or_default();
+        settings.add_profile(self.name.clone(), profile.with_chain(chain));
+        settings.write_config()?;
+
+        Ok(())
+    }
+}
+
+impl ConfigProfileChainAdd {
+    fn run(&self, settings: &mut AppConfig) -> Result<()> {
+        let profile = settings
+            .find_profile(self.name.as_str())
+            .ok_or_else(|| anyhow!("could not find profile {}", self.name))?;
+        let hop = RoleHop::new(
+            self.role_arn.clone(),
+            self.external_id.clone(),
+            self.session_name.clone(),
+            self.tags.iter().cloned().collect(),
+        )?;
+
+        let mut chain = profile.chain();
+        chain.push(hop);
+        settings.add_profile(self.name.clone(), profile.with_chain(chain));
+        settings.write_config()?;
+
+        Ok(())
+    }
+}
+
+impl ConfigProfileChainClear {
+    fn run(&self, settings: &mut AppConfig) -> Result<()> {
+        let profile = settings
+            .find_profile(self.name.as_str())
+            .ok_or_else(|| anyhow!("could not find profile {}", self.name))?;
+        settings.add_profile(self.name.clone(), profile.with_chain(vec![]));
+        settings.write_config()?;
+
+        Ok(())
+    }
+}
+
+impl ConfigProfileList {
+    fn run(&self, settings: &mut AppConfig) -> Result<()> {
+        for (name, profile) in settings.profiles() {
+            println!("{name}");
+            print_profile_value("provider", profile.provider().and_then(value_name));
+            print_profile_value("app-url", profile.app_url());
+            print_profile_value("username", profile.username());
+            print_profile_value("role-arn", profile.role_arn());
+            print_profile_value("region", profile.region());
+            print_profile_value("mfa", profile.mfa());
+            print_profile_value("mfa-provider", profile.mfa_provider());
+            print_profile_value(
+                "session-duration",
+                profile.session_duration().map(|d| d.to_string()),
+            );
+            print_profile_value("output", profile.output().and_then(value_name));
+            let endpoints = profile.endpoints();
+            print_profile_value("partition", endpoints.partition().and_then(value_name));
+            print_profile_value("sts-region", endpoints.sts_region());
+            print_profile_value("sts-endpoint", endpoints.sts_endpoint());
+            print_profile_value("sso-portal-url", endpoints.sso_portal_url());
+            for hop in profile.chain() {
+                print_profile_value("chain", Some(hop.role_arn()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ConfigProfileRemove {
+    fn run(&self, settings: &mut AppConfig) -> Result<()> {
+        settings
+            .remove_profile(self.name.as_str())
+            .ok_or_else(|| anyhow!("could not find profile {}", self.name))?;
+        settings.write_config()?;
+
+        Ok(())
+    }
+}
+
+fn print_profile_value(key: &str, value: Option<String>) {
+    if let Some(value) = value {
+        println!("  {key}: {value}");
+    }
+}
+
+fn value_name<T: ValueEnum>(value: T) -> Option<String> {
+    Some(value.to_possible_value()?.get_name().to_string())
+}
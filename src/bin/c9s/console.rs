@@ -0,0 +1,62 @@
+use crate::credentials::AwsCredentialArgs;
+use anyhow::{anyhow, Result};
+use c9s::aws::console::{ConsoleClient, ConsoleOptions};
+use c9s::settings::AppConfig;
+use clap::Parser;
+
+#[derive(Parser)]
+/// Signs into the AWS console as a role, opening it in the browser.
+pub struct Console {
+    #[clap(flatten)]
+    credentials: AwsCredentialArgs,
+    /// The console of the service to open, e.g. `ec2` or `s3`.
+    #[clap(long)]
+    service: Option<String>,
+    /// The duration of the console session in seconds, between 900 and 43200.
+    #[clap(long)]
+    console_duration: Option<i32>,
+    /// Overrides the federation endpoint of the role's partition.
+    #[clap(long)]
+    federation_endpoint: Option<String>,
+    /// Prints the sign-in URL instead of opening it.
+    #[clap(long)]
+    print: bool,
+}
+
+impl Console {
+    pub async fn run(&self, settings: AppConfig) -> Result<()> {
+        let args = self.credentials.with_profile(&settings)?;
+        if args.all_roles() {
+            return Err(anyhow!("the console can only be opened for a single role"));
+        }
+        let options = ConsoleOptions::new(
+            self.service.clone(),
+            args.region(&settings),
+            self.console_duration,
+            self.federation_endpoint.clone(),
+        )?;
+
+        let credentials = args.fetch(&settings).await?;
+        let credential = match credentials.as_slice() {
+            [credential] => credential,
+            _ => {
+                return Err(anyhow!(
+                    "the console needs 1 credential, but got {}",
+                    credentials.len()
+                ))
+            }
+        };
+
+        let client = ConsoleClient::new()?;
+        let url = client.signin_url(credential, "c9s", &options).await?;
+
+        if self.print {
+            println!("{url}");
+        } else if let Err(e) = webbrowser::open(url.as_str()) {
+            eprintln!("could not open the browser: {e}");
+            println!("{url}");
+        }
+
+        Ok(())
+    }
+}
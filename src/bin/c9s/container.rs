@@ -0,0 +1,169 @@
+use crate::refresh::SharedCredential;
+use anyhow::Result;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use rand::distributions::{Alphanumeric, DistString};
+use std::convert::Infallible;
+use std::rc::Rc;
+use tokio::net::TcpListener;
+
+/// A random token which clients must send in the Authorization header.
+pub fn authorization_token() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 32)
+}
+
+/// Serves the current credential like the ECS container credentials endpoint, which the AWS SDKs
+/// read through `AWS_CONTAINER_CREDENTIALS_FULL_URI` and `AWS_CONTAINER_AUTHORIZATION_TOKEN`.
+///
+/// Must be called within a [`tokio::task::LocalSet`].
+pub async fn serve(listener: TcpListener, token: String, current: SharedCredential) -> Result<()> {
+    listen(listener, move |request| respond(request, &token, &current)).await
+}
+
+/// Answers the HTTP requests of every connection on the listener with `respond`.
+///
+/// Must be called within a [`tokio::task::LocalSet`].
+pub async fn listen<F>(listener: TcpListener, respond: F) -> Result<()>
+where
+    F: Fn(&Request<Incoming>) -> Response<Full<Bytes>> + 'static,
+{
+    let respond = Rc::new(respond);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let respond = respond.clone();
+
+        tokio::task::spawn_local(async move {
+            let service = service_fn(move |request| {
+                let response = respond(&request);
+                async move { Ok::<_, Infallible>(response) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                eprintln!("could not answer credentials request: {e}");
+            }
+        });
+    }
+}
+
+/// Compares the bytes in a time which doesn't depend on where they differ, so that the token
+/// can't be guessed byte by byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn respond<B>(
+    request: &Request<B>,
+    token: &str,
+    current: &SharedCredential,
+) -> Response<Full<Bytes>> {
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .is_some_and(|value| constant_time_eq(value.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return response(StatusCode::UNAUTHORIZED, String::new());
+    }
+
+    let credential = current.borrow();
+    let body = serde_json::json!({
+        "AccessKeyId": credential.access_key_id(),
+        "SecretAccessKey": credential.secret_access_key(),
+        "Token": credential.session_token(),
+        "Expiration": credential.expiration(),
+        "RoleArn": credential.role_arn(),
+    });
+
+    response(StatusCode::OK, body.to_string())
+}
+
+pub fn response(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+
+    response
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use c9s::aws::Credential;
+    use http_body_util::BodyExt;
+    use std::cell::RefCell;
+
+    pub(crate) fn credential() -> SharedCredential {
+        let credential: Credential = serde_json::from_value(serde_json::json!({
+            "secretAccessKey": "TheSecretAccessKey",
+            "accessKeyId": "TheAccessKeyId",
+            "sessionToken": "TheSessionToken",
+            "roleArn": "arn:aws:iam::000222111000:role/team/Admin",
+            "expiration": "2030-01-01T00:00:00Z",
+        }))
+        .unwrap();
+
+        Rc::new(RefCell::new(credential))
+    }
+
+    pub(crate) async fn text(response: Response<Full<Bytes>>) -> String {
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    pub(crate) async fn body(response: Response<Full<Bytes>>) -> serde_json::Value {
+        serde_json::from_str(text(response).await.as_str()).unwrap()
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"TheToken", b"TheToken"));
+        assert!(!constant_time_eq(b"TheToken", b"TheTokem"));
+        assert!(!constant_time_eq(b"TheToken", b"TheToke"));
+        assert!(!constant_time_eq(b"", b"TheToken"));
+    }
+
+    #[tokio::test]
+    async fn test_require_the_token() {
+        let current = credential();
+        let request = |token: Option<&str>| {
+            let mut request = Request::builder().uri("/");
+            if let Some(token) = token {
+                request = request.header(AUTHORIZATION, token);
+            }
+            request.body(()).unwrap()
+        };
+
+        let missing = respond(&request(None), "TheToken", &current);
+        let wrong = respond(&request(Some("OtherToken")), "TheToken", &current);
+        let authorized = respond(&request(Some("TheToken")), "TheToken", &current);
+
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(authorized.status(), StatusCode::OK);
+        let body = body(authorized).await;
+        assert_eq!(body["AccessKeyId"], "TheAccessKeyId");
+        assert_eq!(body["Token"], "TheSessionToken");
+        assert_eq!(body["Expiration"], "2030-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_random_tokens() {
+        let token = authorization_token();
+
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, authorization_token());
+    }
+}
@@ -1,13 +1,23 @@
+use crate::agent;
+use crate::role_picker;
 use crate::utils;
 use anyhow::{anyhow, Result};
-use c9s::aws::Credential;
+use c9s::aws::role_filter::{RoleFilter, RolePattern};
+use c9s::aws::shared_files::{self, SharedFile};
+use c9s::aws::sts::{RoleHop, StsClient};
+use c9s::aws::{Credential, Role, SsoPortalEndpoint, SsoPortalToken};
+use c9s::cache::accounts::LearnedAccounts;
+use c9s::cache::credential_key::CredentialKey;
 use c9s::okta::okta_client::{MfaSelection, OktaClient};
-use c9s::settings::{AppConfig, OktaMfa, SsoProvider};
+use c9s::okta::session::OktaSession;
+use c9s::settings::{
+    AppConfig, AwsAliases, AwsEndpoints, OktaAuthenticator, OktaMfa, OutputOptions, Profile,
+    SsoProvider,
+};
 use clap::Parser;
-use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use time::format_description::well_known::Rfc3339;
-use time::OffsetDateTime;
+use std::future::Future;
 
 #[derive(Parser)]
 pub struct Credentials {
@@ -20,37 +30,87 @@ enum CredentialsSubCommands {
     Aws(AwsCredentials),
 }
 
-#[derive(ValueEnum, PartialEq, Debug, Clone, Copy, Default)]
-enum OutputOptions {
-    #[default]
-    Env,
-    AwsProfile,
-}
-
 #[derive(Parser)]
+/// Prints credentials for AWS roles.
 struct AwsCredentials {
+    #[clap(flatten)]
+    args: AwsCredentialArgs,
+    #[clap(long, value_enum)]
+    output: Option<OutputOptions>,
+    /// The section name used by `--output aws-credentials-file`.
+    ///
+    /// `{account_id}` and `{role_name}` are replaced with the values of each role.
+    #[clap(long, default_value = "{account_id}-{role_name}")]
+    aws_profile_template: String,
+}
+
+#[derive(Parser, Clone, Serialize, Deserialize)]
+pub struct AwsCredentialArgs {
+    #[clap(flatten)]
+    host: AwsHostArgs,
+    #[clap(short, long, conflicts_with_all = ["role", "account", "role_name"])]
+    role_arn: Option<String>,
+    #[clap(flatten)]
+    filter: RoleFilterArgs,
+    /// Assumes every role the user has, instead of picking one.
+    #[clap(long, conflicts_with = "role_arn")]
+    all_roles: bool,
+    #[clap(long)]
+    cached: bool,
+    /// The session duration in seconds, between 900 and 43200, capped by the SessionDuration
+    /// Okta allows. Only used by `okta-aws`, AWS SSO sets the duration per permission set.
+    #[clap(long, value_parser = clap::value_parser!(i32).range(900..=43200))]
+    duration: Option<i32>,
+    /// The roles of the profile's chain, assumed after the role.
+    #[clap(skip)]
+    chain: Vec<RoleHop>,
+}
+
+/// Selects roles by their alias, or by patterns which all matching roles are assumed for.
+///
+/// Patterns are globs like `*:role/ReadOnly`, or regular expressions prefixed with `re:`.
+#[derive(Parser, Clone, Default, Serialize, Deserialize)]
+pub struct RoleFilterArgs {
+    /// The role by its alias, a short name or `account-alias/role-name`, or a pattern matching
+    /// the role ARN or `account-alias/role-name`.
+    #[clap(long)]
+    role: Option<String>,
+    /// A pattern matching the account id, name or alias.
+    #[clap(long)]
+    account: Option<String>,
+    /// A pattern matching the role name.
+    #[clap(long)]
+    role_name: Option<String>,
+}
+
+/// The arguments to find the Okta host and to authenticate against it.
+#[derive(Parser, Clone, Serialize, Deserialize)]
+pub struct AwsHostArgs {
+    /// A profile from the settings, its values are used for all arguments which aren't given.
+    #[clap(long)]
+    profile: Option<String>,
     #[clap(long)]
     app_url: Option<String>,
     #[clap(short, long)]
     username: Option<String>,
     #[clap(short, long)]
     with_password: bool,
-    #[clap(short, long)]
-    role_arn: Option<String>,
     #[clap(long)]
     region: Option<String>,
     #[clap(short, long)]
     mfa: Option<String>,
     #[clap(long)]
     mfa_provider: Option<String>,
-    #[clap(long, value_enum)]
-    output: Option<OutputOptions>,
     #[clap(long)]
     enable_desktop_notifications: bool,
-    #[clap(long)]
-    cached: bool,
     #[clap(long, value_enum)]
     sso_provider: Option<SsoProvider>,
+    #[clap(flatten)]
+    endpoints: AwsEndpoints,
+    /// Set while credentials are renewed in the background, where nobody can answer prompts.
+    #[clap(skip)]
+    #[serde(skip)]
+    renewing: bool,
 }
 
 impl Credentials {
@@ -63,66 +123,517 @@ impl Credentials {
 
 impl AwsCredentials {
     async fn run(&self, settings: AppConfig) -> Result<()> {
-        if let Some(credential) =
-            cached_credential(self.role_arn.clone(), settings.keyring_enabled())
-        {
-            print_credentials(&[credential], self.output)?;
+        let args = self.args.with_profile(&settings)?;
+        let output = match self.output {
+            Some(output) => Some(output),
+            None => args.profile_output(&settings),
+        };
+
+        let aws_credentials = args.fetch(&settings).await?;
+        print_credentials(
+            &aws_credentials,
+            output,
+            self.aws_profile_template.as_str(),
+            &utils::aws_aliases(&settings),
+        )?;
+
+        Ok(())
+    }
+}
+
+impl AwsCredentialArgs {
+    /// Fills all arguments which weren't given on the command line from the selected profile.
+    pub fn with_profile(&self, settings: &AppConfig) -> Result<Self> {
+        let profile = match self.host.profile(settings)? {
+            Some(profile) => profile,
+            None => return Ok(self.clone()),
+        };
 
-            return Ok(());
+        Ok(Self {
+            host: self.host.merge(&profile),
+            role_arn: self.role_arn.clone().or_else(|| profile.role_arn()),
+            filter: self.filter.clone(),
+            all_roles: self.all_roles,
+            cached: self.cached,
+            duration: self.duration.or_else(|| profile.session_duration()),
+            chain: profile.chain(),
+        })
+    }
+
+    fn profile_output(&self, settings: &AppConfig) -> Option<OutputOptions> {
+        self.host.profile(settings).ok()??.output()
+    }
+
+    /// Resolves the credentials through the agent, if one is running.
+    ///
+    /// The role picker needs the user's terminal, so picking a role never goes through the agent.
+    pub async fn fetch(&self, settings: &AppConfig) -> Result<Vec<Credential>> {
+        let picks_role = self.role_arn.is_none() && self.filter.is_empty() && !self.all_roles;
+        if !picks_role {
+            if let Some(credentials) = agent::request_credentials(self).await? {
+                return Ok(credentials);
+            }
         }
 
-        let aws_settings = self.find_settings(&settings)?;
+        self.fetch_local(settings).await
+    }
 
-        let password = utils::get_password(
-            aws_settings.app_url.clone(),
-            aws_settings.username.clone(),
-            self.with_password,
-            settings.keyring_enabled(),
+    /// Renews the credentials without prompting the user, which only works while the Okta
+    /// session is valid.
+    pub async fn renew(&self, settings: &AppConfig) -> Result<Vec<Credential>> {
+        let mut args = self.clone();
+        args.host.renewing = true;
+
+        args.fetch_local(settings).await
+    }
+
+    /// Resolves the credentials, either from the cache or by authenticating against Okta.
+    ///
+    /// Without a role, the user picks one in a terminal, unless all roles are requested. With a
+    /// role chain, only the credential of its last role is returned.
+    pub async fn fetch_local(&self, settings: &AppConfig) -> Result<Vec<Credential>> {
+        if self.chain.is_empty() {
+            return self.fetch_roles(settings).await;
+        }
+
+        Ok(vec![self.fetch_chain(settings).await?])
+    }
+
+    /// Assumes the roles of the chain one after another, continuing from the last role whose
+    /// credential is still cached.
+    async fn fetch_chain(&self, settings: &AppConfig) -> Result<Credential> {
+        let source_arn = match self.role_selection(settings)? {
+            RoleSelection::Role(role_arn) => role_arn,
+            _ => return Err(anyhow!("a role chain needs the role-arn of its first role")),
+        };
+        let aws_settings = self.host.find_settings(settings)?;
+
+        // every hop is cached under the path of roles leading to it
+        let mut keys = vec![];
+        let mut key = self.credential_key(&aws_settings, &source_arn);
+        for hop in &self.chain {
+            key = key.chained(&hop.role_arn());
+            keys.push(key.clone());
+        }
+
+        let cached = keys.iter().enumerate().rev().find_map(|(i, key)| {
+            cached_credential(key, settings).map(|credential| (i + 1, credential))
+        });
+        let (assumed, mut credential) = match cached {
+            Some(cached) => cached,
+            None => match self.fetch_roles(settings).await?.as_slice() {
+                [credential] => (0, credential.clone()),
+                credentials => {
+                    return Err(anyhow!(
+                        "a role chain needs 1 credential to start from, but got {}",
+                        credentials.len()
+                    ))
+                }
+            },
+        };
+
+        let sts_client = StsClient::new(
+            aws_settings
+                .endpoints
+                .sts_options()
+                .with_duration(self.duration),
         )?;
+        for (hop, key) in self.chain.iter().zip(&keys).skip(assumed) {
+            credential = sts_client.assume_role_chained(&credential, hop).await?;
+            utils::set_cached_credential(key, &credential, settings)?;
+        }
 
-        let client = OktaClient::new(self.enable_desktop_notifications)?;
+        Ok(credential)
+    }
+
+    async fn fetch_roles(&self, settings: &AppConfig) -> Result<Vec<Credential>> {
+        let selection = self.role_selection(settings)?;
+        let aws_settings = self.host.find_settings(settings)?;
+        if let RoleSelection::Role(role_arn) = &selection {
+            let key = self.credential_key(&aws_settings, role_arn);
+            if let Some(credential) = cached_credential(&key, settings) {
+                return Ok(vec![credential]);
+            }
+        }
+
+        let client = self.host.client()?;
 
         let aws_credentials = match aws_settings.provider {
             SsoProvider::OktaAws => {
+                let session = self.host.session(settings, &aws_settings, &client).await?;
+                let role_arn = match &selection {
+                    RoleSelection::Pick => {
+                        let roles = client
+                            .aws_roles(aws_settings.app_url.clone(), &session)
+                            .await?;
+                        Some(
+                            role_picker::pick_role(roles, &utils::aws_aliases(settings))?
+                                .role_arn(),
+                        )
+                    }
+                    selection => selection.role_arn(),
+                };
                 client
                     .aws_credentials(
-                        aws_settings.username,
-                        password,
-                        aws_settings.app_url,
-                        self.role_arn.clone(),
-                        aws_settings.mfa,
-                        aws_settings.mfa_provider,
+                        aws_settings.app_url.clone(),
+                        &session,
+                        role_arn,
+                        &selection.filter(),
+                        aws_settings
+                            .endpoints
+                            .sts_options()
+                            .with_duration(self.duration.or(aws_settings.session_duration)),
                     )
                     .await?
             }
             SsoProvider::OktaAwsSso => {
+                let endpoint = aws_settings.sso_portal_endpoint()?;
+                self.host
+                    .with_sso_portal_token(settings, &aws_settings, &client, &endpoint, |token| {
+                        let selection = selection.clone();
+                        let (client, endpoint) = (&client, &endpoint);
+                        async move {
+                            let role_arn = match &selection {
+                                RoleSelection::Pick => {
+                                    let roles = client.aws_sso_roles(endpoint, &token).await?;
+                                    learn_account_aliases(&roles, settings);
+                                    Some(
+                                        role_picker::pick_role(
+                                            roles,
+                                            &utils::aws_aliases(settings),
+                                        )?
+                                        .role_arn(),
+                                    )
+                                }
+                                selection => selection.role_arn(),
+                            };
+
+                            Ok(client
+                                .aws_sso_credentials(
+                                    endpoint,
+                                    &token,
+                                    role_arn,
+                                    &selection.filter(),
+                                )
+                                .await?)
+                        }
+                    })
+                    .await?
+            }
+        };
+
+        if let (RoleSelection::Role(_) | RoleSelection::Pick, [credential]) =
+            (&selection, aws_credentials.as_slice())
+        {
+            if let Some(role_arn) = credential.role_arn() {
+                let key = self.credential_key(&aws_settings, &role_arn);
+                utils::set_cached_credential(&key, credential, settings)?;
+            }
+        }
+
+        Ok(aws_credentials)
+    }
+
+    /// The key the credential of the role is cached under.
+    fn credential_key(&self, aws_settings: &AwsSettings, role_arn: &str) -> CredentialKey {
+        CredentialKey::new(
+            aws_settings.provider,
+            aws_settings.app_url.clone(),
+            aws_settings.username.clone(),
+            role_arn.to_string(),
+        )
+        .with_options(
+            self.duration.or(aws_settings.session_duration),
+            aws_settings.endpoints.sts_region(),
+        )
+    }
+
+    fn role_selection(&self, settings: &AppConfig) -> Result<RoleSelection> {
+        if let Some(role_arn) = self.filter.role_alias(settings, &self.host)? {
+            return Ok(RoleSelection::Role(role_arn));
+        }
+        let filter = self.filter.role_filter(settings)?;
+        if !filter.is_empty() {
+            return Ok(RoleSelection::Filter(filter));
+        }
+
+        match (&self.role_arn, self.all_roles) {
+            (Some(role_arn), _) => Ok(RoleSelection::Role(role_arn.clone())),
+            (None, true) => Ok(RoleSelection::All),
+            (None, false) if role_picker::is_interactive() => Ok(RoleSelection::Pick),
+            (None, false) => Err(anyhow!("please supply a role-arn, or --all-roles")),
+        }
+    }
+
+    pub fn all_roles(&self) -> bool {
+        self.all_roles
+    }
+
+    /// The arguments to renew the credential with, pinned to its role so that a role picked by
+    /// the user isn't picked again.
+    pub fn pinned_to(&self, credential: &Credential) -> Self {
+        let mut args = self.clone();
+        if let (true, Some(role_arn)) = (self.chain.is_empty(), credential.role_arn()) {
+            args.role_arn = Some(role_arn);
+            args.filter = RoleFilterArgs::default();
+        }
+
+        args
+    }
+
+    /// The region configured for these credentials, if any.
+    pub fn region(&self, settings: &AppConfig) -> Option<String> {
+        match &self.host.region {
+            Some(region) => Some(region.clone()),
+            None => self.host.find_settings(settings).ok()?.region,
+        }
+    }
+}
+
+/// Which roles to assume.
+#[derive(Clone)]
+enum RoleSelection {
+    Role(String),
+    Pick,
+    Filter(RoleFilter),
+    All,
+}
+
+impl RoleSelection {
+    /// The role ARN to assume, or `None` for all roles passing the filter.
+    fn role_arn(&self) -> Option<String> {
+        match self {
+            RoleSelection::Role(role_arn) => Some(role_arn.clone()),
+            _ => None,
+        }
+    }
+
+    fn filter(&self) -> RoleFilter {
+        match self {
+            RoleSelection::Filter(filter) => filter.clone(),
+            _ => RoleFilter::default(),
+        }
+    }
+}
+
+impl RoleFilterArgs {
+    fn is_empty(&self) -> bool {
+        self.role.is_none() && self.account.is_none() && self.role_name.is_none()
+    }
+
+    /// The ARN of the role given by its ARN or its alias, unless `--role` is a pattern.
+    fn role_alias(&self, settings: &AppConfig, host: &AwsHostArgs) -> Result<Option<String>> {
+        let role = match &self.role {
+            Some(role) if !RolePattern::is_pattern(role) => role,
+            _ => return Ok(None),
+        };
+        let partition = host
+            .find_settings(settings)
+            .ok()
+            .and_then(|aws_settings| aws_settings.endpoints.partition())
+            .unwrap_or_default();
+
+        Ok(Some(
+            utils::aws_aliases(settings).resolve_role(role, partition)?,
+        ))
+    }
+
+    /// The filter of the patterns, a `--role` alias is left for [`RoleFilterArgs::role_alias`].
+    pub fn role_filter(&self, settings: &AppConfig) -> Result<RoleFilter> {
+        let role = self
+            .role
+            .as_deref()
+            .filter(|role| RolePattern::is_pattern(role));
+        let filter = RoleFilter::new(role, self.account.as_deref(), self.role_name.as_deref())?;
+
+        Ok(filter.with_account_aliases(utils::aws_aliases(settings).accounts()))
+    }
+
+    /// The roles matching the alias or the patterns, or all roles without either.
+    pub fn apply(
+        &self,
+        settings: &AppConfig,
+        host: &AwsHostArgs,
+        roles: Vec<Role>,
+    ) -> Result<Vec<Role>> {
+        if let Some(role_arn) = self.role_alias(settings, host)? {
+            return Ok(roles
+                .into_iter()
+                .filter(|role| role.role_arn() == role_arn)
+                .collect());
+        }
+
+        let filter = self.role_filter(settings)?;
+        if filter.is_empty() {
+            return Ok(roles);
+        }
+
+        Ok(filter.apply(roles)?)
+    }
+}
+
+impl AwsHostArgs {
+    /// Fills all arguments which weren't given on the command line from the selected profile.
+    pub fn with_profile(&self, settings: &AppConfig) -> Result<Self> {
+        match self.profile(settings)? {
+            Some(profile) => Ok(self.merge(&profile)),
+            None => Ok(self.clone()),
+        }
+    }
+
+    fn profile(&self, settings: &AppConfig) -> Result<Option<Profile>> {
+        match &self.profile {
+            Some(name) => {
+                let profile = settings
+                    .find_profile(name)
+                    .ok_or_else(|| anyhow!("could not find profile {}", name))?;
+
+                Ok(Some(profile))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn merge(&self, profile: &Profile) -> Self {
+        Self {
+            profile: self.profile.clone(),
+            app_url: self.app_url.clone().or_else(|| profile.app_url()),
+            username: self.username.clone().or_else(|| profile.username()),
+            with_password: self.with_password,
+            region: self.region.clone().or_else(|| profile.region()),
+            mfa: self.mfa.clone().or_else(|| profile.mfa()),
+            mfa_provider: self.mfa_provider.clone().or_else(|| profile.mfa_provider()),
+            enable_desktop_notifications: self.enable_desktop_notifications,
+            sso_provider: self.sso_provider.or_else(|| profile.provider()),
+            endpoints: self.endpoints.or(&profile.endpoints()),
+            renewing: self.renewing,
+        }
+    }
+
+    /// Lists the roles the user can assume, without generating credentials for them.
+    pub async fn roles(&self, settings: &AppConfig) -> Result<(AwsSettings, Vec<Role>)> {
+        let aws_settings = self.find_settings(settings)?;
+        let client = self.client()?;
+
+        let roles = match aws_settings.provider {
+            SsoProvider::OktaAws => {
+                let session = self.session(settings, &aws_settings, &client).await?;
+                client
+                    .aws_roles(aws_settings.app_url.clone(), &session)
+                    .await?
+            }
+            SsoProvider::OktaAwsSso => {
+                let endpoint = aws_settings.sso_portal_endpoint()?;
+                self.with_sso_portal_token(settings, &aws_settings, &client, &endpoint, |token| {
+                    let (client, endpoint) = (&client, &endpoint);
+                    async move {
+                        let roles = client.aws_sso_roles(endpoint, &token).await?;
+                        learn_account_aliases(&roles, settings);
+                        Ok(roles)
+                    }
+                })
+                .await?
+            }
+        };
+
+        Ok((aws_settings, roles))
+    }
+
+    fn client(&self) -> Result<OktaClient> {
+        Ok(OktaClient::new(self.enable_desktop_notifications)?)
+    }
+
+    /// Creates an Okta session for the user.
+    ///
+    /// The session of an earlier login is reused while Okta accepts it, otherwise the user
+    /// authenticates with their password and MFA.
+    async fn session(
+        &self,
+        settings: &AppConfig,
+        aws_settings: &AwsSettings,
+        client: &OktaClient,
+    ) -> Result<OktaSession> {
+        let app_url = aws_settings.app_url.clone();
+        let username = aws_settings.username.clone();
+
+        let cached_session = match self.with_password {
+            true => None,
+            false => utils::get_cached_session(&app_url, &username, settings).unwrap_or_default(),
+        };
+        let session = match cached_session {
+            Some(session) => client.resume_session(app_url.clone(), session).await?,
+            None => None,
+        };
+
+        let session = match session {
+            Some(session) => session,
+            None if self.renewing => {
+                return Err(anyhow!(
+                    "the Okta session ended, log in again to renew credentials"
+                ))
+            }
+            None => {
+                let password = utils::get_password(
+                    app_url.clone(),
+                    username.clone(),
+                    self.with_password,
+                    settings,
+                )?;
+
                 client
-                    .aws_sso_credentials(
-                        aws_settings.username,
+                    .login(
+                        app_url.clone(),
+                        username.clone(),
                         password,
-                        aws_settings.app_url,
-                        aws_settings
-                            .region
-                            .ok_or_else(|| anyhow!("missing region"))?,
-                        self.role_arn.clone(),
                         aws_settings.mfa,
-                        aws_settings.mfa_provider,
+                        aws_settings.mfa_provider.clone(),
+                        aws_settings.authenticator,
                     )
                     .await?
             }
         };
+        utils::set_cached_session(&app_url, &username, &session, settings)?;
 
-        print_credentials(&aws_credentials, self.output)?;
-        if let Some(role_arn) = &self.role_arn {
-            if aws_credentials.len() == 1 {
-                let credential = aws_credentials
-                    .first()
-                    .ok_or_else(|| anyhow!("failed to get credential"))?;
-                utils::set_cached_credential(role_arn, credential, settings.keyring_enabled())?;
+        Ok(session)
+    }
+
+    /// Calls the AWS SSO portal with the cached portal token. Without a token, or when the portal
+    /// rejects it, the user logs into the portal again through Okta.
+    async fn with_sso_portal_token<T, F, Fut>(
+        &self,
+        settings: &AppConfig,
+        aws_settings: &AwsSettings,
+        client: &OktaClient,
+        endpoint: &SsoPortalEndpoint,
+        call: F,
+    ) -> Result<T>
+    where
+        F: Fn(SsoPortalToken) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let portal_url = endpoint.url();
+        let username = aws_settings.username.as_str();
+
+        let cached_token = match self.with_password {
+            true => None,
+            false => utils::get_cached_sso_portal_token(&portal_url, username, settings)
+                .unwrap_or_default(),
+        };
+        // a rejected token is replaced with the new one below
+        if let Some(token) = cached_token.filter(|token| !token.is_expired()) {
+            match call(token).await {
+                Err(e) if is_unauthorized(&e) => {}
+                result => return result,
             }
         }
 
-        Ok(())
+        let session = self.session(settings, aws_settings, client).await?;
+        let token = client
+            .sso_portal_token(aws_settings.app_url.clone(), &session, endpoint)
+            .await?;
+        utils::set_cached_sso_portal_token(&portal_url, username, &token, settings)?;
+
+        call(token).await
     }
 
     fn find_settings(&self, settings: &AppConfig) -> Result<AwsSettings> {
@@ -131,6 +642,9 @@ impl AwsCredentials {
         let mut region = None;
         let mfa;
         let mfa_provider;
+        let endpoints;
+        let authenticator;
+        let mut session_duration = None;
 
         let provider = self
             .sso_provider
@@ -145,6 +659,17 @@ impl AwsCredentials {
 
                 mfa = get_mfa_option(self.mfa.clone(), &default_settings);
                 mfa_provider = get_mfa_provider(self.mfa_provider.clone(), &default_settings);
+                endpoints = match &default_settings {
+                    Some(host) => self.endpoints.or(&host.endpoints()),
+                    None => self.endpoints.clone(),
+                };
+                session_duration = default_settings
+                    .as_ref()
+                    .and_then(|host| host.session_duration());
+                authenticator = default_settings
+                    .as_ref()
+                    .map(|host| host.authenticator())
+                    .unwrap_or_default();
                 app_url = match self.app_url.clone() {
                     None => default_settings
                         .clone()
@@ -168,6 +693,14 @@ impl AwsCredentials {
 
                 mfa = get_mfa_option(self.mfa.clone(), &default_settings);
                 mfa_provider = get_mfa_provider(self.mfa_provider.clone(), &default_settings);
+                endpoints = match &default_settings {
+                    Some(host) => self.endpoints.or(&host.endpoints()),
+                    None => self.endpoints.clone(),
+                };
+                authenticator = default_settings
+                    .as_ref()
+                    .map(|host| host.authenticator())
+                    .unwrap_or_default();
 
                 app_url = match self.app_url.clone() {
                     None => default_settings
@@ -204,17 +737,35 @@ impl AwsCredentials {
             mfa,
             mfa_provider,
             provider,
+            endpoints,
+            session_duration,
+            authenticator,
         })
     }
 }
 
-struct AwsSettings {
-    app_url: String,
-    username: String,
-    region: Option<String>,
-    mfa: Option<MfaSelection>,
-    mfa_provider: Option<String>,
-    provider: SsoProvider,
+pub struct AwsSettings {
+    pub app_url: String,
+    pub username: String,
+    pub region: Option<String>,
+    pub mfa: Option<MfaSelection>,
+    pub mfa_provider: Option<String>,
+    pub provider: SsoProvider,
+    pub endpoints: AwsEndpoints,
+    pub session_duration: Option<i32>,
+    pub authenticator: OktaAuthenticator,
+}
+
+impl AwsSettings {
+    /// The AWS SSO portal of the `okta-aws-sso` provider.
+    pub fn sso_portal_endpoint(&self) -> Result<SsoPortalEndpoint> {
+        let region = self
+            .region
+            .clone()
+            .ok_or_else(|| anyhow!("missing region"))?;
+
+        Ok(self.endpoints.sso_portal_endpoint(region))
+    }
 }
 
 fn get_mfa_option<T: OktaMfa>(
@@ -243,31 +794,45 @@ fn get_mfa_provider<T: OktaMfa>(
     }
 }
 
-fn cached_credential(role_arn: Option<String>, keyring_enabled: bool) -> Option<Credential> {
-    if let Some(role_arn) = role_arn {
-        let credential =
-            utils::get_cached_credential(&role_arn, keyring_enabled).unwrap_or_default();
-        if let Some(credential) = &credential {
-            let expires = OffsetDateTime::parse(credential.expiration().as_str(), &Rfc3339).ok()?;
-            let now = OffsetDateTime::now_utc();
-            if now > expires {
-                return None;
-            }
-        }
-
-        return credential;
+/// Stores the account names AWS SSO returned, which serve as aliases of accounts without one.
+///
+/// They are only a convenience, so failing to store them doesn't fail the command.
+fn learn_account_aliases(roles: &[Role], settings: &AppConfig) {
+    if let Err(e) =
+        LearnedAccounts::from_settings(settings).and_then(|learned| learned.learn(roles))
+    {
+        eprintln!("warning: could not store the account names: {e}");
     }
+}
+
+fn is_unauthorized(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<c9s::Error>()
+        .is_some_and(|e| e.is_unauthorized())
+}
 
-    None
+/// The cached credential, unless it expires within the refresh window.
+fn cached_credential(key: &CredentialKey, settings: &AppConfig) -> Option<Credential> {
+    let credential = utils::get_cached_credential(key, settings).unwrap_or_default()?;
+
+    (!credential.expires_within(settings.refresh_window())).then_some(credential)
 }
 
-fn print_credentials(aws_credentials: &[Credential], output: Option<OutputOptions>) -> Result<()> {
+fn print_credentials(
+    aws_credentials: &[Credential],
+    output: Option<OutputOptions>,
+    aws_profile_template: &str,
+    aliases: &AwsAliases,
+) -> Result<()> {
     match output.unwrap_or_default() {
         OutputOptions::Env => {
             for credential in aws_credentials {
                 let role_arn = credential
                     .role_arn()
                     .ok_or_else(|| anyhow!("role arn missing for credential"))?;
+                if let Some(alias) = aliases.display_name(&role_arn) {
+                    println!("export C9S_ROLE_ALIAS=\"{alias}\"");
+                }
                 println!(
                     "export AWS_ROLE_ARN=\"{}\"\nexport AWS_ACCESS_KEY_ID=\"{}\"\nexport AWS_SECRET_ACCESS_KEY=\"{}\"\nexport AWS_SESSION_TOKEN=\"{}\"\n",
                     role_arn, credential.access_key_id(), credential.secret_access_key(), credential.session_token()
@@ -294,6 +859,23 @@ fn print_credentials(aws_credentials: &[Credential], output: Option<OutputOption
 
             println!("{json}")
         }
+        OutputOptions::AwsCredentialsFile => {
+            let mut profiles = vec![];
+            for credential in aws_credentials {
+                let role_arn = credential
+                    .role_arn()
+                    .ok_or_else(|| anyhow!("role arn missing for credential"))?;
+                let profile_name = shared_files::profile_name(aws_profile_template, &role_arn)?;
+                profiles.push((profile_name, credential.clone()));
+            }
+
+            let file = SharedFile::credentials()?;
+            file.set_credentials(&profiles)?;
+
+            for (profile_name, _) in profiles {
+                eprintln!("wrote profile {} to {:?}", profile_name, file.path());
+            }
+        }
     }
 
     Ok(())
@@ -0,0 +1,332 @@
+use crate::credentials::AwsCredentialArgs;
+use crate::{container, refresh, utils};
+use anyhow::{anyhow, Result};
+use c9s::aws::Credential;
+use c9s::settings::{AppConfig, AwsAliases};
+use clap::Parser;
+use std::process::ExitStatus;
+use tokio::net::TcpListener;
+use tokio::process::{Child, Command};
+use tokio::task::LocalSet;
+
+#[derive(Parser)]
+/// Runs a command with AWS credentials set in its environment.
+pub struct Exec {
+    #[clap(flatten)]
+    credentials: AwsCredentialArgs,
+    /// Renews the credentials before they expire, handing them to the command through a local
+    /// container credentials endpoint instead of static environment variables.
+    #[clap(long)]
+    refresh: bool,
+    /// The command to run and its arguments, given after `--`.
+    #[clap(required = true, last = true)]
+    command: Vec<String>,
+}
+
+impl Exec {
+    /// Runs the command and returns its exit code.
+    pub async fn run(&self, settings: AppConfig) -> Result<i32> {
+        let args = self.credentials.with_profile(&settings)?;
+        if args.all_roles() {
+            return Err(anyhow!("a command can only run with a single role"));
+        }
+        if self.refresh {
+            utils::keep_secrets_in_memory();
+        }
+
+        let credentials = args.fetch(&settings).await?;
+        if credentials.len() != 1 {
+            return Err(anyhow!(
+                "command should return 1 credential, but got {}",
+                credentials.len()
+            ));
+        }
+        let credential = credentials
+            .first()
+            .ok_or_else(|| anyhow!("failed to get credential"))?;
+        let region = args.region(&settings);
+
+        let (program, program_args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| anyhow!("please supply a command"))?;
+
+        let mut command = Command::new(program);
+        command.args(program_args).envs(environment(
+            credential,
+            region,
+            &utils::aws_aliases(&settings),
+        ));
+
+        if !self.refresh {
+            command.envs(key_environment(credential));
+            let child = command
+                .spawn()
+                .map_err(|e| anyhow!("could not run {program}: {e}"))?;
+            return Ok(exit_code(wait(child).await?));
+        }
+
+        let local = LocalSet::new();
+        local
+            .run_until(async move {
+                let listener = TcpListener::bind("127.0.0.1:0").await?;
+                let token = container::authorization_token();
+                use_container_endpoint(&mut command, &listener, &token)?;
+
+                let current = refresh::keep_fresh(args, settings, credential.clone());
+                tokio::task::spawn_local(container::serve(listener, token, current));
+
+                let child = command
+                    .spawn()
+                    .map_err(|e| anyhow!("could not run {program}: {e}"))?;
+                Ok(exit_code(wait(child).await?))
+            })
+            .await
+    }
+}
+
+/// Variables inherited from the parent which AWS SDKs check before the container credentials
+/// endpoint, so that the child would use other or stale credentials.
+const SHADOWING_VARIABLES: [&str; 6] = [
+    "AWS_ACCESS_KEY_ID",
+    "AWS_SECRET_ACCESS_KEY",
+    "AWS_SESSION_TOKEN",
+    "AWS_CREDENTIAL_EXPIRATION",
+    "AWS_PROFILE",
+    "AWS_CONTAINER_CREDENTIALS_RELATIVE_URI",
+];
+
+/// Points the child's AWS SDK to the local container credentials endpoint, removing the
+/// variables which would take precedence over it.
+fn use_container_endpoint(
+    command: &mut Command,
+    listener: &TcpListener,
+    token: &str,
+) -> Result<()> {
+    let address = listener.local_addr()?;
+    for variable in SHADOWING_VARIABLES {
+        command.env_remove(variable);
+    }
+    command
+        .env(
+            "AWS_CONTAINER_CREDENTIALS_FULL_URI",
+            format!("http://{address}/"),
+        )
+        .env("AWS_CONTAINER_AUTHORIZATION_TOKEN", token);
+
+    Ok(())
+}
+
+/// The static credentials handed to the child process.
+fn key_environment(credential: &Credential) -> Vec<(String, String)> {
+    vec![
+        (
+            String::from("AWS_ACCESS_KEY_ID"),
+            credential.access_key_id(),
+        ),
+        (
+            String::from("AWS_SECRET_ACCESS_KEY"),
+            credential.secret_access_key(),
+        ),
+        (
+            String::from("AWS_SESSION_TOKEN"),
+            credential.session_token(),
+        ),
+        (
+            String::from("AWS_CREDENTIAL_EXPIRATION"),
+            credential.expiration(),
+        ),
+    ]
+}
+
+/// The environment variables describing the role, handed to the child process.
+fn environment(
+    credential: &Credential,
+    region: Option<String>,
+    aliases: &AwsAliases,
+) -> Vec<(String, String)> {
+    let mut env = vec![];
+
+    if let Some(role_arn) = credential.role_arn() {
+        if let Some(alias) = aliases.display_name(&role_arn) {
+            env.push((String::from("C9S_ROLE_ALIAS"), alias));
+        }
+        env.push((String::from("AWS_ROLE_ARN"), role_arn));
+    }
+
+    if let Some(region) = region {
+        env.push((String::from("AWS_REGION"), region.clone()));
+        env.push((String::from("AWS_DEFAULT_REGION"), region));
+    }
+
+    env
+}
+
+/// Waits for the child to exit while forwarding signals sent to c9s.
+///
+/// SIGINT and SIGQUIT are only caught: the terminal already delivers them to the whole foreground
+/// process group, so forwarding them would make the child see them twice.
+#[cfg(unix)]
+async fn wait(mut child: Child) -> Result<ExitStatus> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let pid = child
+        .id()
+        .ok_or_else(|| anyhow!("could not get the child's process id"))?
+        as libc::pid_t;
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut quit = signal(SignalKind::quit())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut user_defined1 = signal(SignalKind::user_defined1())?;
+    let mut user_defined2 = signal(SignalKind::user_defined2())?;
+
+    loop {
+        let forward = tokio::select! {
+            status = child.wait() => return Ok(status?),
+            _ = interrupt.recv() => None,
+            _ = quit.recv() => None,
+            _ = terminate.recv() => Some(libc::SIGTERM),
+            _ = hangup.recv() => Some(libc::SIGHUP),
+            _ = user_defined1.recv() => Some(libc::SIGUSR1),
+            _ = user_defined2.recv() => Some(libc::SIGUSR2),
+        };
+
+        if let Some(signal) = forward {
+            // SAFETY: kill has no memory safety requirements, the pid belongs to our child.
+            unsafe {
+                libc::kill(pid, signal);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait(mut child: Child) -> Result<ExitStatus> {
+    Ok(child.wait().await?)
+}
+
+/// Maps the child's exit status to our own exit code, following the shell's 128 + signal
+/// convention for children killed by a signal.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+
+    status.code().unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const ROLE_ARN: &str = "arn:aws:iam::000222111000:role/Admin";
+
+    fn credential(role_arn: Option<&str>) -> Credential {
+        serde_json::from_value(serde_json::json!({
+            "secretAccessKey": "TheSecretAccessKey",
+            "accessKeyId": "TheAccessKeyId",
+            "sessionToken": "TheSessionToken",
+            "roleArn": role_arn,
+            "expiration": "2030-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_environment() {
+        let mut aliases = AwsAliases::default();
+        aliases
+            .set_account(String::from("000222111000"), String::from("prod"))
+            .unwrap();
+
+        let env: HashMap<String, String> = environment(
+            &credential(Some(ROLE_ARN)),
+            Some(String::from("eu-west-1")),
+            &aliases,
+        )
+        .into_iter()
+        .collect();
+        let without_role = environment(&credential(None), None, &aliases);
+
+        assert_eq!(
+            env,
+            HashMap::from([
+                (String::from("C9S_ROLE_ALIAS"), String::from("prod/Admin")),
+                (String::from("AWS_ROLE_ARN"), String::from(ROLE_ARN)),
+                (String::from("AWS_REGION"), String::from("eu-west-1")),
+                (
+                    String::from("AWS_DEFAULT_REGION"),
+                    String::from("eu-west-1")
+                ),
+            ])
+        );
+        assert!(without_role.is_empty());
+    }
+
+    #[test]
+    fn test_key_environment() {
+        let env: HashMap<String, String> = key_environment(&credential(Some(ROLE_ARN)))
+            .into_iter()
+            .collect();
+
+        assert_eq!(env["AWS_ACCESS_KEY_ID"], "TheAccessKeyId");
+        assert_eq!(env["AWS_SECRET_ACCESS_KEY"], "TheSecretAccessKey");
+        assert_eq!(env["AWS_SESSION_TOKEN"], "TheSessionToken");
+        assert_eq!(env["AWS_CREDENTIAL_EXPIRATION"], "2030-01-01T00:00:00Z");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_remove_shadowing_variables() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut command = Command::new("env");
+        command
+            .env("AWS_PROFILE", "other")
+            .env("AWS_ACCESS_KEY_ID", "OtherAccessKeyId")
+            .env("AWS_REGION", "eu-west-1");
+
+        use_container_endpoint(&mut command, &listener, "TheToken").unwrap();
+        let output = command.output().await.unwrap();
+        let env: HashMap<String, String> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        for variable in SHADOWING_VARIABLES {
+            assert!(!env.contains_key(variable), "{variable} is set");
+        }
+        assert_eq!(env["AWS_REGION"], "eu-west-1");
+        assert_eq!(
+            env["AWS_CONTAINER_CREDENTIALS_FULL_URI"],
+            format!("http://{address}/")
+        );
+        assert_eq!(env["AWS_CONTAINER_AUTHORIZATION_TOKEN"], "TheToken");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_code() {
+        use std::os::unix::process::ExitStatusExt;
+
+        assert_eq!(exit_code(ExitStatus::from_raw(0)), 0);
+        assert_eq!(exit_code(ExitStatus::from_raw(3 << 8)), 3);
+        assert_eq!(exit_code(ExitStatus::from_raw(libc::SIGKILL)), 137);
+    }
+
+    #[test]
+    fn test_command_is_required() {
+        assert!(Exec::try_parse_from(["exec", "--refresh"]).is_err());
+        assert!(Exec::try_parse_from(["exec", "--refresh", "--", "aws"]).is_ok());
+    }
+}
@@ -0,0 +1,282 @@
+use crate::container::{self, authorization_token};
+use crate::refresh::SharedCredential;
+use anyhow::Result;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio::net::TcpListener;
+
+const TOKEN_PATH: &str = "/latest/api/token";
+const CREDENTIALS_PATH: &str = "/latest/meta-data/iam/security-credentials/";
+const REGION_PATH: &str = "/latest/meta-data/placement/region";
+
+const TOKEN_TTL_HEADER: &str = "x-aws-ec2-metadata-token-ttl-seconds";
+const TOKEN_HEADER: &str = "x-aws-ec2-metadata-token";
+
+/// IMDSv2 tokens live between a second and 6 hours.
+const TOKEN_TTL_RANGE: std::ops::RangeInclusive<u64> = 1..=21600;
+
+/// The most tokens kept at once, the ones expiring first are dropped for new ones.
+const MAX_TOKENS: usize = 1024;
+
+/// Serves the current credential like the EC2 instance metadata service, which the AWS SDKs
+/// read through `AWS_EC2_METADATA_SERVICE_ENDPOINT`.
+///
+/// Only IMDSv2 is supported: clients first get a session token with `PUT /latest/api/token`.
+/// Must be called within a [`tokio::task::LocalSet`].
+pub async fn serve(
+    listener: TcpListener,
+    region: Option<String>,
+    current: SharedCredential,
+) -> Result<()> {
+    let tokens = RefCell::new(HashMap::new());
+
+    container::listen(listener, move |request| {
+        respond(
+            request,
+            &mut tokens.borrow_mut(),
+            region.as_deref(),
+            &current,
+        )
+    })
+    .await
+}
+
+fn respond<B>(
+    request: &Request<B>,
+    tokens: &mut HashMap<String, Instant>,
+    region: Option<&str>,
+    current: &SharedCredential,
+) -> Response<Full<Bytes>> {
+    let now = Instant::now();
+    tokens.retain(|_, expires_at| *expires_at > now);
+
+    let path = request.uri().path();
+    if path == TOKEN_PATH {
+        return match request.method() {
+            &Method::PUT => new_token(request, tokens),
+            _ => status(StatusCode::METHOD_NOT_ALLOWED),
+        };
+    }
+
+    let authorized = request
+        .headers()
+        .get(TOKEN_HEADER)
+        .and_then(|token| token.to_str().ok())
+        .is_some_and(|token| tokens.contains_key(token));
+    if !authorized {
+        return status(StatusCode::UNAUTHORIZED);
+    }
+    if request.method() != Method::GET {
+        return status(StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    let credential = current.borrow();
+    let role_name = role_name(credential.role_arn());
+    match path.strip_prefix(CREDENTIALS_PATH) {
+        Some("") => return container::response(StatusCode::OK, role_name),
+        Some(name) if name == role_name => {}
+        Some(_) => return status(StatusCode::NOT_FOUND),
+        None => {
+            return match (path, region) {
+                (REGION_PATH, Some(region)) => {
+                    container::response(StatusCode::OK, region.to_string())
+                }
+                _ => status(StatusCode::NOT_FOUND),
+            }
+        }
+    }
+
+    let body = serde_json::json!({
+        "Code": "Success",
+        "LastUpdated": OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+        "Type": "AWS-HMAC",
+        "AccessKeyId": credential.access_key_id(),
+        "SecretAccessKey": credential.secret_access_key(),
+        "Token": credential.session_token(),
+        "Expiration": credential.expiration(),
+    });
+
+    container::response(StatusCode::OK, body.to_string())
+}
+
+/// Hands out a session token for the requested number of seconds. Like EC2, requests which went
+/// through a proxy are rejected.
+fn new_token<B>(
+    request: &Request<B>,
+    tokens: &mut HashMap<String, Instant>,
+) -> Response<Full<Bytes>> {
+    if request.headers().contains_key("x-forwarded-for") {
+        return status(StatusCode::FORBIDDEN);
+    }
+    let ttl = request
+        .headers()
+        .get(TOKEN_TTL_HEADER)
+        .and_then(|ttl| ttl.to_str().ok())
+        .and_then(|ttl| ttl.parse::<u64>().ok())
+        .filter(|ttl| TOKEN_TTL_RANGE.contains(ttl));
+    let ttl = match ttl {
+        Some(ttl) => ttl,
+        None => return status(StatusCode::BAD_REQUEST),
+    };
+
+    if tokens.len() >= MAX_TOKENS {
+        let expiring_first = tokens
+            .iter()
+            .min_by_key(|(_, expires_at)| **expires_at)
+            .map(|(token, _)| token.clone());
+        if let Some(token) = expiring_first {
+            tokens.remove(&token);
+        }
+    }
+
+    let token = authorization_token();
+    tokens.insert(token.clone(), Instant::now() + Duration::from_secs(ttl));
+
+    let mut response = container::response(StatusCode::OK, token);
+    if let Ok(value) = ttl.to_string().parse() {
+        response.headers_mut().insert(TOKEN_TTL_HEADER, value);
+    }
+
+    response
+}
+
+/// The name the role is listed under, the last part of its name without a path.
+fn role_name(role_arn: Option<String>) -> String {
+    role_arn
+        .as_deref()
+        .and_then(|arn| arn.rsplit('/').next())
+        .unwrap_or("c9s")
+        .to_string()
+}
+
+fn status(status: StatusCode) -> Response<Full<Bytes>> {
+    container::response(status, String::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::tests::{body, credential, text};
+
+    fn put_token(ttl: &str) -> Request<()> {
+        Request::put(TOKEN_PATH)
+            .header(TOKEN_TTL_HEADER, ttl)
+            .body(())
+            .unwrap()
+    }
+
+    fn get(path: &str, token: &str) -> Request<()> {
+        Request::get(path)
+            .header(TOKEN_HEADER, token)
+            .body(())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_serve_credentials_with_a_token() {
+        let current = credential();
+        let mut tokens = HashMap::new();
+        let mut respond =
+            |request: &Request<()>| respond(request, &mut tokens, Some("eu-west-1"), &current);
+
+        let without_token = respond(&get(CREDENTIALS_PATH, "NoToken"));
+        let token = respond(&put_token("60"));
+        assert_eq!(token.status(), StatusCode::OK);
+        assert_eq!(token.headers()[TOKEN_TTL_HEADER], "60");
+        let token = text(token).await;
+
+        let roles = respond(&get(CREDENTIALS_PATH, &token));
+        let role = respond(&get(&format!("{CREDENTIALS_PATH}Admin"), &token));
+        let other_role = respond(&get(&format!("{CREDENTIALS_PATH}Other"), &token));
+        let region = respond(&get(REGION_PATH, &token));
+
+        assert_eq!(without_token.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(text(roles).await, "Admin");
+        assert_eq!(other_role.status(), StatusCode::NOT_FOUND);
+        assert_eq!(text(region).await, "eu-west-1");
+        assert_eq!(role.status(), StatusCode::OK);
+        let body = body(role).await;
+        assert_eq!(body["Code"], "Success");
+        assert_eq!(body["AccessKeyId"], "TheAccessKeyId");
+        assert_eq!(body["Token"], "TheSessionToken");
+    }
+
+    #[test]
+    fn test_reject_invalid_token_requests() {
+        let current = credential();
+        let mut tokens = HashMap::new();
+        let mut respond =
+            |request: &Request<()>| respond(request, &mut tokens, None, &current).status();
+        let forwarded = Request::put(TOKEN_PATH)
+            .header(TOKEN_TTL_HEADER, "60")
+            .header("x-forwarded-for", "192.168.0.2")
+            .body(())
+            .unwrap();
+
+        assert_eq!(respond(&put_token("0")), StatusCode::BAD_REQUEST);
+        assert_eq!(respond(&put_token("21601")), StatusCode::BAD_REQUEST);
+        assert_eq!(respond(&put_token("soon")), StatusCode::BAD_REQUEST);
+        assert_eq!(respond(&forwarded), StatusCode::FORBIDDEN);
+        assert_eq!(
+            respond(&Request::get(TOKEN_PATH).body(()).unwrap()),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert!(tokens.is_empty());
+    }
+
+    #[test]
+    fn test_expired_tokens_are_rejected() {
+        let current = credential();
+        let mut tokens = HashMap::from([(
+            String::from("ExpiredToken"),
+            Instant::now() - Duration::from_secs(1),
+        )]);
+
+        let response = respond(
+            &get(CREDENTIALS_PATH, "ExpiredToken"),
+            &mut tokens,
+            None,
+            &current,
+        );
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(tokens.is_empty());
+    }
+
+    #[test]
+    fn test_drop_the_token_expiring_first() {
+        let now = Instant::now();
+        let mut tokens: HashMap<String, Instant> = (0..MAX_TOKENS)
+            .map(|i| {
+                (
+                    format!("Token{i}"),
+                    now + Duration::from_secs(60 + i as u64),
+                )
+            })
+            .collect();
+
+        let response = new_token(&put_token("60"), &mut tokens);
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(tokens.len(), MAX_TOKENS);
+        assert!(!tokens.contains_key("Token0"));
+        assert!(tokens.contains_key("Token1"));
+    }
+
+    #[test]
+    fn test_role_name() {
+        assert_eq!(
+            role_name(Some(String::from(
+                "arn:aws:iam::000222111000:role/team/Admin"
+            ))),
+            "Admin"
+        );
+        assert_eq!(role_name(None), "c9s");
+    }
+}
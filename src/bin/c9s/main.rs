@@ -1,11 +1,28 @@
+mod agent;
+mod cache;
 mod config;
+mod console;
+mod container;
 mod credentials;
+mod exec;
+mod imds;
+mod refresh;
+mod role_picker;
+mod roles;
+mod serve;
 mod utils;
 
+use crate::agent::Agent;
+use crate::cache::{Cache, Password};
 use crate::config::Config;
+use crate::console::Console;
 use crate::credentials::Credentials;
+use crate::exec::Exec;
+use crate::roles::Roles;
+use crate::serve::Serve;
 use anyhow::Result;
 use c9s::settings::AppConfig;
+use c9s::Error;
 use clap::Parser;
 
 #[derive(Parser)]
@@ -20,9 +37,16 @@ struct Opts {
 
 #[derive(Parser)]
 enum SubCommand {
+    Agent(Agent),
+    Cache(Cache),
     Config(Config),
+    Console(Console),
     Creds(Credentials),
+    Exec(Exec),
     Licenses(Licenses),
+    Password(Password),
+    Roles(Roles),
+    Serve(Serve),
 }
 
 #[derive(Parser)]
@@ -38,15 +62,60 @@ impl Licenses {
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     let opt: Opts = Opts::parse();
+
+    match run(opt).await {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            std::process::exit(exit_code(&e));
+        }
+    }
+}
+
+async fn run(opt: Opts) -> Result<i32> {
     let mut settings = AppConfig::read_config()?;
 
     match opt.sub_command {
-        SubCommand::Config(val) => val.run(&mut settings)?,
+        SubCommand::Agent(val) => val.run().await?,
+        SubCommand::Cache(val) => val.run(settings)?,
+        SubCommand::Config(val) => val.run(&mut settings).await?,
+        SubCommand::Console(val) => val.run(settings).await?,
         SubCommand::Creds(val) => val.run(settings).await?,
+        SubCommand::Exec(val) => return val.run(settings).await,
         SubCommand::Licenses(val) => val.run()?,
+        SubCommand::Password(val) => val.run(settings)?,
+        SubCommand::Roles(val) => val.run(settings).await?,
+        SubCommand::Serve(val) => val.run(settings).await?,
     }
 
-    Ok(())
+    Ok(0)
+}
+
+/// Maps the kind of error to an exit code, so that wrapper scripts can react to it.
+fn exit_code(error: &anyhow::Error) -> i32 {
+    match error.downcast_ref::<Error>() {
+        Some(Error::InvalidCredentials) => 10,
+        Some(Error::MfaRejected) => 11,
+        Some(Error::MfaTimeout) => 12,
+        Some(Error::FactorNotFound) => 13,
+        Some(Error::WebAuthn(_)) => 14,
+        Some(Error::MfaNumberMismatch) => 15,
+        Some(Error::MfaInvalidPasscode) => 16,
+        Some(Error::HttpStatus { .. }) => 20,
+        Some(Error::Http(_)) => 21,
+        Some(Error::UnexpectedResponse(_)) => 22,
+        Some(Error::SamlParse(_)) => 30,
+        Some(Error::StsFailure(_)) => 31,
+        Some(Error::RoleNotFound(_)) => 32,
+        Some(Error::InvalidArn(_)) => 33,
+        Some(Error::Config(_)) => 40,
+        Some(Error::Url(_)) => 41,
+        Some(Error::UserInput(_)) => 42,
+        Some(Error::Io(_)) => 50,
+        Some(Error::Json(_)) => 51,
+        Some(Error::Cache(_)) => 52,
+        None => 1,
+    }
 }
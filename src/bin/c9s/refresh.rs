@@ -0,0 +1,71 @@
+use crate::agent;
+use crate::credentials::AwsCredentialArgs;
+use c9s::aws::Credential;
+use c9s::settings::AppConfig;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// The credential currently handed out, replaced whenever it is renewed.
+pub type SharedCredential = Rc<RefCell<Credential>>;
+
+/// How long to wait before trying again after a failed renewal, and at least between renewals.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Renews the credential in the background shortly before it expires, as configured by the
+/// refresh window. Must be called within a [`tokio::task::LocalSet`].
+pub fn keep_fresh(
+    args: AwsCredentialArgs,
+    settings: AppConfig,
+    credential: Credential,
+) -> SharedCredential {
+    let args = args.pinned_to(&credential);
+    let current = Rc::new(RefCell::new(credential));
+
+    let shared = current.clone();
+    tokio::task::spawn_local(async move {
+        loop {
+            let wait = until_renewal(&shared.borrow(), &settings);
+            tokio::time::sleep(wait).await;
+
+            match renew(&args, &settings).await {
+                Ok(credential) => *shared.borrow_mut() = credential,
+                Err(e) => {
+                    eprintln!("could not renew credentials: {e:#}");
+                    tokio::time::sleep(RETRY_INTERVAL).await;
+                }
+            }
+        }
+    });
+
+    current
+}
+
+/// Renews the credential through the agent if one is running, otherwise with the cached Okta
+/// session.
+async fn renew(args: &AwsCredentialArgs, settings: &AppConfig) -> anyhow::Result<Credential> {
+    let credentials = match agent::request_credentials(args).await? {
+        Some(credentials) => credentials,
+        None => args.renew(settings).await?,
+    };
+
+    credentials
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("got no credential"))
+}
+
+/// The time until the credential enters the refresh window.
+fn until_renewal(credential: &Credential, settings: &AppConfig) -> Duration {
+    let renew_at = match OffsetDateTime::parse(credential.expiration().as_str(), &Rfc3339) {
+        Ok(expiration) => expiration - settings.refresh_window(),
+        Err(_) => return RETRY_INTERVAL,
+    };
+    let wait = renew_at - OffsetDateTime::now_utc();
+
+    Duration::try_from(wait)
+        .unwrap_or_default()
+        .max(RETRY_INTERVAL)
+}
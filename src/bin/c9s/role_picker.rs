@@ -0,0 +1,53 @@
+use anyhow::{anyhow, Result};
+use c9s::aws::Role;
+use c9s::settings::AwsAliases;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::FuzzySelect;
+use std::io::IsTerminal;
+
+/// Whether the user can pick a role. The picker is drawn on stderr, so that it also works when
+/// stdout is captured, e.g. by `eval "$(c9s creds aws)"`.
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Lets the user pick a role, searching through the account ids, account names and role names.
+///
+/// Accounts without a name from the provider are shown with their alias.
+pub fn pick_role(mut roles: Vec<Role>, aliases: &AwsAliases) -> Result<Role> {
+    if roles.is_empty() {
+        return Err(anyhow!("there are no roles to choose from"));
+    }
+    let accounts = aliases.accounts();
+    let account_name = |role: &Role| {
+        role.account_name()
+            .or_else(|| accounts.get(&role.account_id()).cloned())
+    };
+    roles.sort_by_key(|role| (account_name(role), role.account_id(), role.role_name()));
+
+    let items: Vec<String> = roles
+        .iter()
+        .map(|role| label(role, account_name(role)))
+        .collect();
+    let selection = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Role")
+        .items(&items)
+        .default(0)
+        .max_length(15)
+        .interact_opt()?
+        .ok_or_else(|| anyhow!("no role selected"))?;
+
+    Ok(roles.swap_remove(selection))
+}
+
+fn label(role: &Role, account_name: Option<String>) -> String {
+    match account_name {
+        Some(account_name) => format!(
+            "{}  {}  {}",
+            role.account_id(),
+            account_name,
+            role.role_name()
+        ),
+        None => format!("{}  {}", role.account_id(), role.role_name()),
+    }
+}
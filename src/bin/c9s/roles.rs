@@ -0,0 +1,150 @@
+use crate::credentials::{AwsHostArgs, RoleFilterArgs};
+use crate::utils;
+use anyhow::Result;
+use c9s::aws::Role;
+use c9s::settings::{AppConfig, AwsAliases};
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+
+#[derive(Parser)]
+/// Lists the roles a user can assume, without generating credentials.
+pub struct Roles {
+    #[clap(subcommand)]
+    sub_command: RolesSubCommands,
+}
+
+#[derive(Parser)]
+enum RolesSubCommands {
+    Aws(AwsRoles),
+}
+
+#[derive(Parser)]
+/// Lists the AWS roles of an Okta host.
+struct AwsRoles {
+    #[clap(flatten)]
+    host: AwsHostArgs,
+    #[clap(flatten)]
+    filter: RoleFilterArgs,
+    #[clap(long, value_enum, default_value_t)]
+    output: RolesOutput,
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+enum RolesOutput {
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+/// A role as it is printed, values which the provider doesn't know are left empty.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RoleRow {
+    account_id: String,
+    account_name: Option<String>,
+    account_email: Option<String>,
+    alias: Option<String>,
+    role_name: String,
+    role_arn: String,
+    principal_arn: Option<String>,
+}
+
+impl Roles {
+    pub async fn run(&self, settings: AppConfig) -> Result<()> {
+        match &self.sub_command {
+            RolesSubCommands::Aws(val) => val.run(settings).await,
+        }
+    }
+}
+
+impl AwsRoles {
+    async fn run(&self, settings: AppConfig) -> Result<()> {
+        let host = self.host.with_profile(&settings)?;
+        let (_, roles) = host.roles(&settings).await?;
+        let roles = self.filter.apply(&settings, &host, roles)?;
+
+        let aliases = utils::aws_aliases(&settings);
+        let mut rows: Vec<RoleRow> = roles
+            .iter()
+            .map(|role| RoleRow::new(role, &aliases))
+            .collect();
+        rows.sort_by(|a, b| (&a.account_id, &a.role_name).cmp(&(&b.account_id, &b.role_name)));
+
+        match self.output {
+            RolesOutput::Table => print_table(&rows),
+            RolesOutput::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+            RolesOutput::Csv => print_csv(&rows),
+        }
+
+        Ok(())
+    }
+}
+
+impl RoleRow {
+    fn new(role: &Role, aliases: &AwsAliases) -> Self {
+        Self {
+            account_id: role.account_id(),
+            account_name: role.account_name(),
+            account_email: role.account_email(),
+            alias: aliases.display_name(&role.role_arn()),
+            role_name: role.role_name(),
+            role_arn: role.role_arn(),
+            principal_arn: role.principal_arn(),
+        }
+    }
+
+    const HEADERS: [&'static str; 7] = [
+        "ACCOUNT ID",
+        "ACCOUNT NAME",
+        "ACCOUNT EMAIL",
+        "ALIAS",
+        "ROLE NAME",
+        "ROLE ARN",
+        "PRINCIPAL ARN",
+    ];
+
+    fn values(&self) -> [String; 7] {
+        [
+            self.account_id.clone(),
+            self.account_name.clone().unwrap_or_default(),
+            self.account_email.clone().unwrap_or_default(),
+            self.alias.clone().unwrap_or_default(),
+            self.role_name.clone(),
+            self.role_arn.clone(),
+            self.principal_arn.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+/// Prints the rows aligned in columns, leaving out columns without any value.
+fn print_table(rows: &[RoleRow]) {
+    let values: Vec<[String; 7]> = rows.iter().map(RoleRow::values).collect();
+    let columns: Vec<usize> = (0..RoleRow::HEADERS.len())
+        .filter(|&column| values.iter().any(|row| !row[column].is_empty()))
+        .collect();
+
+    let headers: Vec<&str> = columns.iter().map(|&c| RoleRow::HEADERS[c]).collect();
+    let values: Vec<Vec<String>> = values
+        .iter()
+        .map(|row| columns.iter().map(|&c| row[c].clone()).collect())
+        .collect();
+    utils::print_table(&headers, &values);
+}
+
+fn print_csv(rows: &[RoleRow]) {
+    println!("account_id,account_name,account_email,alias,role_name,role_arn,principal_arn");
+    for row in rows {
+        let values: Vec<String> = row.values().iter().map(|v| csv_field(v)).collect();
+        println!("{}", values.join(","));
+    }
+}
+
+/// Quotes a CSV field when it contains a separator, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
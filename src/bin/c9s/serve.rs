@@ -0,0 +1,133 @@
+use crate::credentials::AwsCredentialArgs;
+use crate::{container, imds, refresh, utils};
+use anyhow::{anyhow, Result};
+use c9s::settings::AppConfig;
+use clap::Parser;
+use futures::future::LocalBoxFuture;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::task::LocalSet;
+
+#[derive(Parser)]
+/// Serves the credentials of a role on a local container credentials endpoint, renewing them
+/// before they expire.
+///
+/// Prints the environment variables which point the AWS SDKs to the endpoint.
+pub struct Serve {
+    #[clap(flatten)]
+    credentials: AwsCredentialArgs,
+    /// The address to listen on, a random port on localhost by default.
+    #[clap(long, default_value = "127.0.0.1:0")]
+    address: SocketAddr,
+    /// Allows listening on addresses other hosts can reach. Anyone with the token gets the
+    /// credentials.
+    #[clap(long, conflicts_with = "imds")]
+    allow_remote: bool,
+    /// The token clients must send in the Authorization header, a random one by default.
+    #[clap(long, conflicts_with = "imds")]
+    token: Option<String>,
+    /// Serves the credentials like the EC2 instance metadata service (IMDSv2) instead, for tools
+    /// which only read credentials from there.
+    #[clap(long)]
+    imds: bool,
+}
+
+impl Serve {
+    pub async fn run(&self, settings: AppConfig) -> Result<()> {
+        self.check_address()?;
+        let args = self.credentials.with_profile(&settings)?;
+        if args.all_roles() {
+            return Err(anyhow!("credentials can only be served for a single role"));
+        }
+        utils::keep_secrets_in_memory();
+
+        let credentials = args.fetch(&settings).await?;
+        let credential = match credentials.as_slice() {
+            [credential] => credential.clone(),
+            _ => {
+                return Err(anyhow!(
+                    "serving needs 1 credential, but got {}",
+                    credentials.len()
+                ))
+            }
+        };
+
+        let listener = TcpListener::bind(self.address).await?;
+        let address = listener.local_addr()?;
+        let region = args.region(&settings);
+
+        let local = LocalSet::new();
+        local
+            .run_until(async move {
+                let current = refresh::keep_fresh(args, settings, credential);
+                let served: LocalBoxFuture<Result<()>> = if self.imds {
+                    println!("export AWS_EC2_METADATA_SERVICE_ENDPOINT=http://{address}/");
+                    Box::pin(imds::serve(listener, region, current))
+                } else {
+                    let token = self
+                        .token
+                        .clone()
+                        .unwrap_or_else(container::authorization_token);
+                    println!("export AWS_CONTAINER_CREDENTIALS_FULL_URI=http://{address}/");
+                    println!("export AWS_CONTAINER_AUTHORIZATION_TOKEN={token}");
+                    Box::pin(container::serve(listener, token, current))
+                };
+
+                tokio::select! {
+                    served = served => served,
+                    _ = tokio::signal::ctrl_c() => Ok(()),
+                }
+            })
+            .await
+    }
+
+    /// Only loopback addresses are allowed, unless remote access is allowed explicitly.
+    fn check_address(&self) -> Result<()> {
+        if self.address.ip().is_loopback() || self.allow_remote {
+            return Ok(());
+        }
+        let hint = match self.imds {
+            true => "listen on a loopback address, the IMDS endpoint has no authentication",
+            false => "listen on a loopback address or pass --allow-remote",
+        };
+
+        Err(anyhow!(
+            "{} can be reached from other hosts, {hint}",
+            self.address
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serve(args: &[&str]) -> Serve {
+        Serve::try_parse_from([&["serve"], args].concat()).unwrap()
+    }
+
+    #[test]
+    fn test_only_listen_on_loopback_addresses() {
+        assert!(serve(&[]).check_address().is_ok());
+        assert!(serve(&["--address", "[::1]:8080"]).check_address().is_ok());
+        assert!(serve(&["--address", "0.0.0.0:8080"])
+            .check_address()
+            .is_err());
+        assert!(serve(&["--address", "0.0.0.0:8080", "--allow-remote"])
+            .check_address()
+            .is_ok());
+
+        let imds = serve(&["--address", "192.168.0.2:80", "--imds"]).check_address();
+        assert!(imds
+            .unwrap_err()
+            .to_string()
+            .contains("the IMDS endpoint has no authentication"));
+    }
+
+    #[test]
+    fn test_allow_remote_conflicts_with_imds() {
+        let parsed = Serve::try_parse_from(["serve", "--imds", "--allow-remote"]);
+
+        assert!(parsed.is_err());
+    }
+}
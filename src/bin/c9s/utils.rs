@@ -1,74 +1,318 @@
 use anyhow::{anyhow, Result};
-use c9s::aws::Credential;
+use c9s::aws::{Credential, SsoPortalToken};
+use c9s::cache;
+use c9s::cache::accounts::LearnedAccounts;
+use c9s::cache::credential_key::CredentialKey;
+use c9s::cache::index::{CacheEntry, CacheIndex, EntryKind};
+use c9s::okta::session::OktaSession;
+use c9s::settings::{AppConfig, AwsAliases, CacheBackend};
+use std::collections::HashMap;
 use std::io::{self, BufRead, Write};
+use std::sync::{Mutex, OnceLock};
 use tmuntaner_keyring::KeyringClient;
 use url::Url;
 
+/// Secrets by their keyring service and user, see [`keep_secrets_in_memory`].
+static MEMORY: OnceLock<Mutex<HashMap<(String, String), String>>> = OnceLock::new();
+
 pub fn get_password(
     app_url: String,
     username: String,
     with_password: bool,
-    keyring_enabled: bool,
+    settings: &AppConfig,
 ) -> Result<String> {
-    let app_domain = Url::parse(app_url.as_str())?;
-    let app_domain = app_domain
-        .domain()
-        .ok_or_else(|| anyhow!("could not find app domain"))?;
-    let service = format!("c9s -- {app_domain}");
+    let keyring_enabled = settings.keyring_enabled();
+    let service = password_service(&app_url)?;
 
     let keyring = KeyringClient::new(username.as_str(), service.as_str(), "c9s")?;
     let password = if keyring_enabled {
-        keyring.get_password()?
+        // forgotten passwords are overwritten with an empty one
+        keyring
+            .get_password()?
+            .filter(|password| !password.is_empty())
     } else {
         None
     };
 
-    let password = match with_password {
-        true => prompt_user_for_password(&keyring, keyring_enabled)?,
-        false => match password {
-            Some(password) => password,
-            None => prompt_user_for_password(&keyring, keyring_enabled)?,
-        },
+    let password = match password {
+        Some(password) if !with_password => password,
+        _ => {
+            let (password, saved) = prompt_user_for_password(&keyring, keyring_enabled)?;
+            if saved {
+                let entry = CacheEntry::new(
+                    EntryKind::Password,
+                    &service,
+                    &username,
+                    CacheBackend::Keyring,
+                )
+                .with_host(app_url);
+                CacheIndex::from_settings(settings)?.record(entry)?;
+            }
+            password
+        }
     };
 
     Ok(password)
 }
 
-pub fn get_cached_credential(role_arn: &str, keyring_enabled: bool) -> Result<Option<Credential>> {
-    let cached_credential = if keyring_enabled {
-        let service = format!("c9s credential -- {role_arn}");
-        let keyring = KeyringClient::new(role_arn, &service, "c9s")?;
-        keyring.get_password()?
-    } else {
-        None
-    };
+/// Removes the saved password of the user from the keyring.
+pub fn forget_password(app_url: &str, username: &str, settings: &AppConfig) -> Result<()> {
+    let service = password_service(app_url)?;
 
-    if let Some(cached_credential) = cached_credential {
-        let credentials: Credential = serde_json::from_str(cached_credential.as_str())?;
+    cache::open(CacheBackend::Keyring, settings)?.delete(&service, username)?;
+    CacheIndex::from_settings(settings)?.remove(&service, username)?;
+
+    Ok(())
+}
 
-        return Ok(Some(credentials));
+/// The cached credential of the key.
+///
+/// A credential cached by an older version under its role only is migrated to the key once the
+/// index shows it, see [`migrate_legacy_credential`].
+pub fn get_cached_credential(
+    key: &CredentialKey,
+    settings: &AppConfig,
+) -> Result<Option<Credential>> {
+    let service = key.service();
+    let index = CacheIndex::from_settings(settings)?;
+    if let Some(legacy) = index.entry(&service, &key.legacy_user())? {
+        migrate_legacy_credential(key, &legacy, settings)?;
     }
 
-    Ok(None)
+    match get_secret(&service, &key.user(), settings)? {
+        Some(credential) => Ok(Some(serde_json::from_str(credential.as_str())?)),
+        None => Ok(None),
+    }
 }
 
 pub fn set_cached_credential(
-    role_arn: &str,
+    key: &CredentialKey,
     credential: &Credential,
-    keyring_enabled: bool,
+    settings: &AppConfig,
 ) -> Result<()> {
-    if keyring_enabled {
-        let service = format!("c9s credential -- {role_arn}");
-        let keyring = KeyringClient::new(role_arn, &service, "c9s")?;
-        let json = serde_json::to_string(credential)?;
-        keyring.set_password(json)?;
+    let json = serde_json::to_string(credential)?;
+
+    set_secret(credential_entry(key, credential, settings), json, settings)
+}
+
+fn credential_entry(
+    key: &CredentialKey,
+    credential: &Credential,
+    settings: &AppConfig,
+) -> CacheEntry {
+    CacheEntry::new(
+        EntryKind::Credential,
+        &key.service(),
+        &key.user(),
+        settings.cache_backend(),
+    )
+    .with_host(key.app_url())
+    .with_role_arn(credential.role_arn())
+    .with_expiration(credential.expiration())
+}
+
+/// Moves a credential cached under its role only to the key, or deletes it once it expired.
+///
+/// The legacy format doesn't tell whose credential it is, so it's only handed to the key of the
+/// Okta host the index recorded for it and of the user configured for that host. Credentials of
+/// other keys stay until their owner asks for them.
+fn migrate_legacy_credential(
+    key: &CredentialKey,
+    legacy: &CacheEntry,
+    settings: &AppConfig,
+) -> Result<()> {
+    let cache = match cache::from_settings(settings)? {
+        Some(cache) if legacy.backend() == settings.cache_backend() => cache,
+        _ => return Ok(()),
+    };
+    let index = CacheIndex::from_settings(settings)?;
+
+    if legacy.is_expired() {
+        cache.delete(&legacy.service(), &legacy.user())?;
+        return Ok(index.remove(&legacy.service(), &legacy.user())?);
+    }
+    let owned = legacy.host() == Some(key.app_url())
+        && configured_username(&key.app_url(), settings) == Some(key.username());
+    if !owned {
+        return Ok(());
+    }
+
+    if let Some(credential) = key.migrate_legacy(cache.as_ref())? {
+        let credential: Credential = serde_json::from_str(credential.as_str())?;
+        index.record(credential_entry(key, &credential, settings))?;
+    }
+    index.remove(&legacy.service(), &legacy.user())?;
+
+    Ok(())
+}
+
+/// The user configured for the Okta host of the AWS or the AWS SSO application.
+pub fn configured_username(app_url: &str, settings: &AppConfig) -> Option<String> {
+    match settings.find_aws_host(app_url.to_string()) {
+        Some(host) => Some(host.username()),
+        None => settings
+            .find_aws_sso_host(app_url.to_string())
+            .map(|host| host.username()),
+    }
+}
+
+/// The Okta session of the user from an earlier login, if there is one.
+pub fn get_cached_session(
+    app_url: &str,
+    username: &str,
+    settings: &AppConfig,
+) -> Result<Option<OktaSession>> {
+    let service = format!("c9s session -- {}", app_domain(app_url)?);
+
+    match get_secret(&service, username, settings)? {
+        Some(session) => Ok(Some(serde_json::from_str(session.as_str())?)),
+        None => Ok(None),
+    }
+}
+
+pub fn set_cached_session(
+    app_url: &str,
+    username: &str,
+    session: &OktaSession,
+    settings: &AppConfig,
+) -> Result<()> {
+    let service = format!("c9s session -- {}", app_domain(app_url)?);
+    let json = serde_json::to_string(session)?;
+    let entry = CacheEntry::new(
+        EntryKind::Session,
+        &service,
+        username,
+        settings.cache_backend(),
+    )
+    .with_host(app_url.to_string())
+    .with_expiration(session.expires_at());
+
+    set_secret(entry, json, settings)
+}
+
+/// The AWS SSO portal token of the user from an earlier login, if there is one.
+pub fn get_cached_sso_portal_token(
+    portal_url: &str,
+    username: &str,
+    settings: &AppConfig,
+) -> Result<Option<SsoPortalToken>> {
+    let service = format!("c9s sso portal token -- {portal_url}");
+
+    match get_secret(&service, username, settings)? {
+        Some(token) => Ok(Some(serde_json::from_str(token.as_str())?)),
+        None => Ok(None),
+    }
+}
+
+pub fn set_cached_sso_portal_token(
+    portal_url: &str,
+    username: &str,
+    token: &SsoPortalToken,
+    settings: &AppConfig,
+) -> Result<()> {
+    let service = format!("c9s sso portal token -- {portal_url}");
+    let json = serde_json::to_string(token)?;
+    let entry = CacheEntry::new(
+        EntryKind::SsoPortalToken,
+        &service,
+        username,
+        settings.cache_backend(),
+    )
+    .with_host(portal_url.to_string())
+    .with_expiration(token.expires_at());
+
+    set_secret(entry, json, settings)
+}
+
+/// Keeps cached secrets in memory for the lifetime of the process, in front of the cache.
+/// Used by processes which renew credentials without prompting, which also need the session when
+/// caching is disabled.
+pub fn keep_secrets_in_memory() {
+    MEMORY.get_or_init(Default::default);
+}
+
+fn get_secret(service: &str, username: &str, settings: &AppConfig) -> Result<Option<String>> {
+    if let Some(memory) = MEMORY.get() {
+        let memory = memory.lock().unwrap();
+        if let Some(secret) = memory.get(&(service.to_string(), username.to_string())) {
+            return Ok(Some(secret.clone()));
+        }
     }
 
+    match cache::from_settings(settings)? {
+        Some(cache) => Ok(cache.get(service, username)?),
+        None => Ok(None),
+    }
+}
+
+/// Caches the secret and records it in the index of the cache.
+///
+/// A cache which can't be written only costs a login the next time, so it doesn't fail the
+/// command.
+fn set_secret(entry: CacheEntry, secret: String, settings: &AppConfig) -> Result<()> {
+    if let Some(memory) = MEMORY.get() {
+        let mut memory = memory.lock().unwrap();
+        memory.insert((entry.service(), entry.user()), secret.clone());
+    }
+
+    let kind = entry.kind();
+    let cached = cache::from_settings(settings).and_then(|cache| match cache {
+        Some(cache) => {
+            cache.set(&entry.service(), &entry.user(), secret.as_str())?;
+            CacheIndex::from_settings(settings)?.record(entry)
+        }
+        None => Ok(()),
+    });
+    if let Err(e) = cached {
+        eprintln!("warning: could not cache the {}: {e}", kind.name());
+    }
+
+    Ok(())
+}
+
+/// Removes the cached secret of the entry from its cache and from the index.
+pub fn delete_secret(entry: &CacheEntry, settings: &AppConfig) -> Result<()> {
+    if let Some(memory) = MEMORY.get() {
+        let mut memory = memory.lock().unwrap();
+        memory.remove(&(entry.service(), entry.user()));
+    }
+
+    cache::open(entry.backend(), settings)?.delete(&entry.service(), &entry.user())?;
+    CacheIndex::from_settings(settings)?.remove(&entry.service(), &entry.user())?;
+
     Ok(())
 }
 
-fn prompt_user_for_password(keyring: &KeyringClient, keyring_enabled: bool) -> Result<String> {
+/// The aliases of the settings, along with the account names learned from AWS SSO.
+pub fn aws_aliases(settings: &AppConfig) -> AwsAliases {
+    let learned = LearnedAccounts::from_settings(settings)
+        .and_then(|learned| learned.accounts())
+        .unwrap_or_default();
+
+    settings.aws_aliases().with_learned_accounts(learned)
+}
+
+/// The keyring service of the saved passwords of the Okta host.
+pub fn password_service(app_url: &str) -> Result<String> {
+    Ok(format!("c9s -- {}", app_domain(app_url)?))
+}
+
+fn app_domain(app_url: &str) -> Result<String> {
+    let app_domain = Url::parse(app_url)?;
+    let app_domain = app_domain
+        .domain()
+        .ok_or_else(|| anyhow!("could not find app domain"))?;
+
+    Ok(app_domain.to_string())
+}
+
+/// Prompts for the password, and whether it was saved in the keyring.
+fn prompt_user_for_password(
+    keyring: &KeyringClient,
+    keyring_enabled: bool,
+) -> Result<(String, bool)> {
     let password = rpassword::prompt_password("Password: ")?;
+    let mut saved = false;
 
     if keyring_enabled {
         eprint!("Save password? (y/n) ");
@@ -81,10 +325,45 @@ fn prompt_user_for_password(keyring: &KeyringClient, keyring_enabled: bool) -> R
 
         if buffer == "y" {
             keyring.set_password(password.clone())?;
+            saved = true;
         }
     }
 
-    Ok(password)
+    Ok((password, saved))
+}
+
+/// Prints the rows aligned in columns below the headers.
+pub fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].len())
+                .chain([headers[column].len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let print_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    };
+
+    print_row(headers.to_vec());
+    for row in rows {
+        print_row(row.iter().map(String::as_str).collect());
+    }
+}
+
+pub fn key_value(s: &str) -> Result<(String, String), &'static str> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err("expected `key=value`"),
+    }
 }
 
 pub fn true_or_false(s: &str) -> Result<bool, &'static str> {
@@ -0,0 +1,108 @@
+use crate::aws::Role;
+use crate::cache::locked;
+use crate::error::{Error, Result};
+use crate::files::write_atomically;
+use crate::settings::AppConfig;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// The account names AWS SSO returned, by their account id, which serve as aliases of accounts
+/// without one in the settings.
+///
+/// They're kept next to the cache instead of in the settings, so that listing roles never
+/// rewrites the settings. Updates are done while holding a lock on a sidecar `.lock` file.
+pub struct LearnedAccounts {
+    path: PathBuf,
+}
+
+impl LearnedAccounts {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn from_settings(settings: &AppConfig) -> Result<Self> {
+        Ok(Self::new(settings.learned_accounts_file()?))
+    }
+
+    pub fn accounts(&self) -> Result<BTreeMap<String, String>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let contents = fs::read_to_string(&self.path)?;
+
+        serde_json::from_str(contents.as_str())
+            .map_err(|e| Error::Cache(format!("could not read {:?}: {e}", self.path)))
+    }
+
+    /// Stores the account names of the roles, the file is only written when a name is new or
+    /// changed.
+    pub fn learn(&self, roles: &[Role]) -> Result<()> {
+        let names: BTreeMap<String, String> = roles
+            .iter()
+            .filter_map(|role| Some((role.account_id(), role.account_name()?)))
+            .filter(|(_, name)| !name.contains('/'))
+            .collect();
+        if names.is_empty() {
+            return Ok(());
+        }
+
+        locked(&self.path, || {
+            let mut accounts = self.accounts()?;
+            let before = accounts.clone();
+            accounts.extend(names);
+            if accounts == before {
+                return Ok(());
+            }
+
+            write_atomically(
+                &self.path,
+                serde_json::to_string_pretty(&accounts)?.as_bytes(),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_learn_accounts() {
+        let directory = tempfile::tempdir().unwrap();
+        let learned = LearnedAccounts::new(directory.path().join("accounts.json"));
+        let role = |account_id: &str, account_name: Option<&str>| {
+            let role = Role::from_arn(&format!("arn:aws:iam::{account_id}:role/Admin")).unwrap();
+            match account_name {
+                Some(account_name) => role.with_account(
+                    &serde_json::from_value(serde_json::json!({
+                        "accountId": account_id,
+                        "accountName": account_name,
+                        "emailAddress": "aws@example.com",
+                    }))
+                    .unwrap(),
+                ),
+                None => role,
+            }
+        };
+
+        let empty = learned.accounts().unwrap();
+        learned
+            .learn(&[
+                role("000222111000", Some("prod")),
+                role("000333111000", None),
+                role("000444111000", Some("team/staging")),
+            ])
+            .unwrap();
+        learned
+            .learn(&[role("000222111000", Some("production"))])
+            .unwrap();
+        let accounts = learned.accounts().unwrap();
+
+        assert!(empty.is_empty());
+        assert_eq!(
+            accounts,
+            BTreeMap::from([(String::from("000222111000"), String::from("production"))])
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::cache::SecretCache;
+use crate::error::Result;
+use crate::settings::SsoProvider;
+
+/// Identifies a cached credential by everything it depends on, so that different users, hosts
+/// and providers don't share credentials of the same role.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CredentialKey {
+    provider: SsoProvider,
+    app_url: String,
+    username: String,
+    role: String,
+    duration: Option<i32>,
+    region: Option<String>,
+}
+
+impl CredentialKey {
+    /// Generates a new [`CredentialKey`], `role` is a role ARN or the path of roles leading to a
+    /// role of a chain.
+    pub fn new(provider: SsoProvider, app_url: String, username: String, role: String) -> Self {
+        Self {
+            provider,
+            app_url,
+            username,
+            role,
+            duration: None,
+            region: None,
+        }
+    }
+
+    /// The requested session duration and STS region, which change the credential STS returns.
+    pub fn with_options(mut self, duration: Option<i32>, region: Option<String>) -> Self {
+        self.duration = duration;
+        self.region = region;
+        self
+    }
+
+    /// The same key for a role assumed with the credential of this one.
+    pub fn chained(&self, role_arn: &str) -> Self {
+        let mut key = self.clone();
+        key.role = format!("{} -> {role_arn}", self.role);
+        key
+    }
+
+    pub fn app_url(&self) -> String {
+        self.app_url.clone()
+    }
+
+    pub fn username(&self) -> String {
+        self.username.clone()
+    }
+
+    pub fn role(&self) -> String {
+        self.role.clone()
+    }
+
+    /// The keyring service of the credential, the same as in the legacy format.
+    pub fn service(&self) -> String {
+        format!("c9s credential -- {}", self.role)
+    }
+
+    /// The keyring user of the credential, everything but the role separated by `|`.
+    pub fn user(&self) -> String {
+        [
+            self.provider.name().to_string(),
+            self.app_url.clone(),
+            self.username.clone(),
+            self.duration
+                .map(|duration| duration.to_string())
+                .unwrap_or_default(),
+            self.region.clone().unwrap_or_default(),
+        ]
+        .join("|")
+    }
+
+    /// The keyring user of the legacy format, which only used the role.
+    pub fn legacy_user(&self) -> String {
+        self.role.clone()
+    }
+
+    /// Moves the credential of the role cached in the legacy format to this key, returning it.
+    ///
+    /// The legacy format doesn't tell whose credential it is, the caller has to make sure that it
+    /// belongs to this key.
+    pub fn migrate_legacy(&self, cache: &dyn SecretCache) -> Result<Option<String>> {
+        let service = self.service();
+        let legacy_user = self.legacy_user();
+        let credential = match cache.get(&service, &legacy_user)? {
+            Some(credential) => credential,
+            None => return Ok(None),
+        };
+        cache.set(&service, &self.user(), &credential)?;
+        cache.delete(&service, &legacy_user)?;
+
+        Ok(Some(credential))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::encrypted_file::{CacheKey, EncryptedFileCache};
+
+    #[test]
+    fn test_credential_key() {
+        let key = CredentialKey::new(
+            SsoProvider::OktaAws,
+            String::from("https://example.okta.com/home/amazon_aws/0oa/272"),
+            String::from("the-user"),
+            String::from("arn:aws:iam::000222111000:role/Hub"),
+        )
+        .with_options(Some(3600), Some(String::from("eu-west-1")));
+        let other_user = CredentialKey::new(
+            SsoProvider::OktaAws,
+            String::from("https://example.okta.com/home/amazon_aws/0oa/272"),
+            String::from("other-user"),
+            String::from("arn:aws:iam::000222111000:role/Hub"),
+        );
+
+        assert_eq!(
+            key.service(),
+            "c9s credential -- arn:aws:iam::000222111000:role/Hub"
+        );
+        assert_eq!(
+            key.user(),
+            "okta-aws|https://example.okta.com/home/amazon_aws/0oa/272|the-user|3600|eu-west-1"
+        );
+        assert_eq!(key.service(), other_user.service());
+        assert_ne!(key.user(), other_user.user());
+        assert_eq!(key.legacy_user(), "arn:aws:iam::000222111000:role/Hub");
+
+        let chained = key.chained("arn:aws:iam::000333111000:role/Spoke");
+        assert_eq!(
+            chained.role(),
+            "arn:aws:iam::000222111000:role/Hub -> arn:aws:iam::000333111000:role/Spoke"
+        );
+        assert_eq!(chained.user(), key.user());
+    }
+
+    #[test]
+    fn test_migrate_legacy_credential() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = EncryptedFileCache::new(
+            directory.path().join("cache.enc"),
+            CacheKey::Passphrase(String::from("ThePassphrase")),
+        );
+        let key = CredentialKey::new(
+            SsoProvider::OktaAws,
+            String::from("https://example.okta.com/home/amazon_aws/0oa/272"),
+            String::from("the-user"),
+            String::from("arn:aws:iam::000222111000:role/Hub"),
+        );
+        // cached before the key included the user
+        cache
+            .set(&key.service(), &key.legacy_user(), "TheCredential")
+            .unwrap();
+
+        let migrated = key.migrate_legacy(&cache).unwrap();
+        let credential = cache.get(&key.service(), &key.user()).unwrap();
+        let legacy = cache.get(&key.service(), &key.legacy_user()).unwrap();
+        let migrated_again = key.migrate_legacy(&cache).unwrap();
+
+        assert_eq!(migrated, Some(String::from("TheCredential")));
+        assert_eq!(credential, Some(String::from("TheCredential")));
+        assert_eq!(legacy, None);
+        assert_eq!(migrated_again, None);
+    }
+}
@@ -0,0 +1,375 @@
+use crate::cache::{locked, SecretCache};
+use crate::error::{Error, Result};
+use crate::files::{create_private_dir, write_atomically, write_private};
+use crate::settings::AppConfig;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The version of the file format, also authenticated with the contents.
+const VERSION: u32 = 1;
+
+/// The variable holding the passphrase the key is derived from, instead of a key file.
+pub const PASSPHRASE_VARIABLE: &str = "C9S_CACHE_PASSPHRASE";
+
+/// What the key of the cache comes from.
+#[derive(Clone, Debug)]
+pub enum CacheKey {
+    /// A passphrase, stretched with Argon2id and the salt of the cache file.
+    Passphrase(String),
+    /// A file with a random key, created on first use.
+    KeyFile(PathBuf),
+}
+
+/// Caches secrets in a file encrypted with AES-256-GCM, for machines without a keyring service.
+///
+/// The file is only readable by the user, and changes to it are detected when decrypting. Updates
+/// are done while holding a lock on a sidecar `.lock` file.
+pub struct EncryptedFileCache {
+    path: PathBuf,
+    key: CacheKey,
+    /// The key derived for a salt, so that the passphrase is only stretched once.
+    derived: Mutex<Option<([u8; 16], [u8; 32])>>,
+}
+
+/// The encrypted file, the contents are the secrets by their service and user.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+type Secrets = BTreeMap<String, BTreeMap<String, String>>;
+
+impl EncryptedFileCache {
+    pub fn new(path: PathBuf, key: CacheKey) -> Self {
+        Self {
+            path,
+            key,
+            derived: Mutex::new(None),
+        }
+    }
+
+    /// The cache file from the settings, encrypted with the passphrase in `C9S_CACHE_PASSPHRASE`
+    /// or else with the key file.
+    pub fn from_settings(settings: &AppConfig) -> Result<Self> {
+        let key = match std::env::var(PASSPHRASE_VARIABLE) {
+            Ok(passphrase) if !passphrase.is_empty() => CacheKey::Passphrase(passphrase),
+            _ => CacheKey::KeyFile(settings.cache_key_file()?),
+        };
+
+        Ok(Self::new(settings.cache_file()?, key))
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// The file an unreadable cache is moved to, see [`EncryptedFileCache::update`].
+    pub fn unreadable_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".unreadable");
+        PathBuf::from(path)
+    }
+
+    /// Applies `update` to the secrets and writes them back.
+    ///
+    /// A file which can't be decrypted anymore, because the key changed or it was modified, is
+    /// moved aside to [`EncryptedFileCache::unreadable_path`] before a new cache is started, so
+    /// that it can still be recovered with the old key.
+    fn update<F>(&self, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut Secrets),
+    {
+        locked(&self.path, || {
+            let (salt, mut secrets) = match self.read() {
+                Ok(read) => read,
+                Err(Error::Cache(e)) => {
+                    let unreadable = self.unreadable_path();
+                    fs::rename(&self.path, &unreadable)?;
+                    eprintln!("warning: {e}, moved it to {unreadable:?} and started a new cache");
+                    (new_salt(), Secrets::new())
+                }
+                Err(e) => return Err(e),
+            };
+            update(&mut secrets);
+            self.write(salt, &secrets)
+        })
+    }
+
+    /// Decrypts the secrets and returns them with the salt of the file. Without a file, there are
+    /// no secrets yet and a new salt is generated.
+    fn read(&self) -> Result<([u8; 16], Secrets)> {
+        if !self.path.exists() {
+            return Ok((new_salt(), Secrets::new()));
+        }
+
+        let envelope: Envelope = serde_json::from_str(fs::read_to_string(&self.path)?.as_str())
+            .map_err(|_| Error::Cache(format!("{:?} is not a c9s cache", self.path)))?;
+        if envelope.version != VERSION {
+            return Err(Error::Cache(format!(
+                "unsupported cache version {}",
+                envelope.version
+            )));
+        }
+        let salt: [u8; 16] = decode(&envelope.salt)?;
+        let nonce: [u8; 12] = decode(&envelope.nonce)?;
+        let ciphertext = STANDARD
+            .decode(envelope.ciphertext)
+            .map_err(|e| Error::Cache(e.to_string()))?;
+
+        let plaintext = self
+            .cipher(salt)?
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: ciphertext.as_slice(),
+                    aad: &aad(),
+                },
+            )
+            .map_err(|_| {
+                Error::Cache(format!(
+                    "could not decrypt {:?}, it was modified or the key changed",
+                    self.path
+                ))
+            })?;
+
+        Ok((salt, serde_json::from_slice(plaintext.as_slice())?))
+    }
+
+    fn write(&self, salt: [u8; 16], secrets: &Secrets) -> Result<()> {
+        let mut nonce = [0; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let plaintext = serde_json::to_vec(secrets)?;
+
+        let ciphertext = self
+            .cipher(salt)?
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext.as_slice(),
+                    aad: &aad(),
+                },
+            )
+            .map_err(|_| Error::Cache(String::from("could not encrypt the cache")))?;
+        let envelope = Envelope {
+            version: VERSION,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+
+        write_atomically(&self.path, serde_json::to_string(&envelope)?.as_bytes())
+    }
+
+    fn cipher(&self, salt: [u8; 16]) -> Result<Aes256Gcm> {
+        let mut derived = self.derived.lock().unwrap();
+        let key = match *derived {
+            Some((derived_salt, key)) if derived_salt == salt => key,
+            _ => {
+                let key = self.derive_key(&salt)?;
+                *derived = Some((salt, key));
+                key
+            }
+        };
+
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+    }
+
+    fn derive_key(&self, salt: &[u8]) -> Result<[u8; 32]> {
+        match &self.key {
+            CacheKey::Passphrase(passphrase) => {
+                let mut key = [0; 32];
+                Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+                    .map_err(|e| Error::Cache(e.to_string()))?;
+                Ok(key)
+            }
+            CacheKey::KeyFile(path) => {
+                if !path.exists() && self.path.exists() {
+                    eprintln!(
+                        "warning: the key file {path:?} of the cache {:?} is missing, creating a new key",
+                        self.path
+                    );
+                }
+                read_or_create_key_file(path)
+            }
+        }
+    }
+}
+
+impl SecretCache for EncryptedFileCache {
+    fn get(&self, service: &str, user: &str) -> Result<Option<String>> {
+        let (_, secrets) = self.read()?;
+
+        Ok(secrets
+            .get(service)
+            .and_then(|users| users.get(user))
+            .cloned())
+    }
+
+    fn set(&self, service: &str, user: &str, secret: &str) -> Result<()> {
+        self.update(|secrets| {
+            secrets
+                .entry(service.to_string())
+                .or_default()
+                .insert(user.to_string(), secret.to_string());
+        })
+    }
+
+    fn delete(&self, service: &str, user: &str) -> Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+
+        self.update(|secrets| {
+            if let Some(users) = secrets.get_mut(service) {
+                users.remove(user);
+                if users.is_empty() {
+                    secrets.remove(service);
+                }
+            }
+        })
+    }
+}
+
+fn new_salt() -> [u8; 16] {
+    let mut salt = [0; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    salt
+}
+
+/// The data authenticated along with the secrets.
+fn aad() -> Vec<u8> {
+    format!("c9s cache v{VERSION}").into_bytes()
+}
+
+fn decode<const N: usize>(value: &str) -> Result<[u8; N]> {
+    STANDARD
+        .decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| Error::Cache(String::from("the cache file is malformed")))
+}
+
+/// Reads the base64 encoded key from the file, or creates the file with a random key.
+fn read_or_create_key_file(path: &Path) -> Result<[u8; 32]> {
+    if path.exists() {
+        let contents = fs::read_to_string(path)?;
+        return decode(contents.trim())
+            .map_err(|_| Error::Cache(format!("{path:?} doesn't hold a 32 byte key")));
+    }
+
+    let mut key = [0; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    if let Some(directory) = path.parent() {
+        create_private_dir(directory)?;
+    }
+    write_private(path, STANDARD.encode(key).as_bytes())?;
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_get_secrets() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("cache.enc");
+        let cache = EncryptedFileCache::new(
+            path.clone(),
+            CacheKey::Passphrase(String::from("ThePassphrase")),
+        );
+
+        assert_eq!(cache.get("c9s session", "the-user").unwrap(), None);
+        cache.set("c9s session", "the-user", "TheSession").unwrap();
+        cache
+            .set("c9s session", "other-user", "OtherSession")
+            .unwrap();
+
+        let cache = EncryptedFileCache::new(
+            path.clone(),
+            CacheKey::Passphrase(String::from("ThePassphrase")),
+        );
+        let session = cache.get("c9s session", "the-user").unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let wrong_key = EncryptedFileCache::new(
+            path.clone(),
+            CacheKey::Passphrase(String::from("WrongPassphrase")),
+        )
+        .get("c9s session", "the-user");
+
+        assert_eq!(session, Some(String::from("TheSession")));
+        assert!(!contents.contains("TheSession"));
+        assert!(matches!(wrong_key, Err(Error::Cache(_))));
+    }
+
+    #[test]
+    fn test_detect_tampering() {
+        let directory = tempfile::tempdir().unwrap();
+        let key_file = directory.path().join("cache.key");
+        let path = directory.path().join("cache.enc");
+        let cache = EncryptedFileCache::new(path.clone(), CacheKey::KeyFile(key_file.clone()));
+        cache
+            .set("c9s credential", "the-role", "TheCredential")
+            .unwrap();
+
+        let mut envelope: Envelope =
+            serde_json::from_str(fs::read_to_string(&path).unwrap().as_str()).unwrap();
+        let mut ciphertext = STANDARD.decode(&envelope.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        envelope.ciphertext = STANDARD.encode(ciphertext);
+        fs::write(&path, serde_json::to_string(&envelope).unwrap()).unwrap();
+
+        let tampered = cache.get("c9s credential", "the-role");
+        #[cfg(unix)]
+        let modes = {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            (mode(&key_file), mode(&path))
+        };
+
+        assert!(matches!(tampered, Err(Error::Cache(_))));
+        #[cfg(unix)]
+        assert_eq!(modes, (0o600, 0o600));
+    }
+
+    #[test]
+    fn test_move_cache_aside_after_key_change() {
+        let directory = tempfile::tempdir().unwrap();
+        let key_file = directory.path().join("cache.key");
+        let path = directory.path().join("cache.enc");
+        let cache = EncryptedFileCache::new(path.clone(), CacheKey::KeyFile(key_file.clone()));
+        cache
+            .set("c9s credential", "the-role", "OldCredential")
+            .unwrap();
+
+        // a lost key file is created again with a new key
+        fs::remove_file(&key_file).unwrap();
+        let cache = EncryptedFileCache::new(path.clone(), CacheKey::KeyFile(key_file.clone()));
+        let old_contents = fs::read_to_string(&path).unwrap();
+        let unreadable = cache.get("c9s credential", "the-role");
+        let set = cache.set("c9s credential", "other-role", "NewCredential");
+        let old = cache.get("c9s credential", "the-role").unwrap();
+        let new = cache.get("c9s credential", "other-role").unwrap();
+        let moved_contents = fs::read_to_string(cache.unreadable_path()).unwrap();
+
+        assert!(matches!(unreadable, Err(Error::Cache(_))));
+        assert!(set.is_ok());
+        assert_eq!(old, None);
+        assert_eq!(new, Some(String::from("NewCredential")));
+        assert_eq!(moved_contents, old_contents);
+    }
+}
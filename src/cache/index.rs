@@ -0,0 +1,226 @@
+use crate::cache::locked;
+use crate::error::{Error, Result};
+use crate::files::write_atomically;
+use crate::settings::{AppConfig, CacheBackend};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use time::format_description::well_known::Rfc3339;
+use time::{Duration, OffsetDateTime};
+
+/// What a cached secret is.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EntryKind {
+    Credential,
+    Session,
+    SsoPortalToken,
+    Password,
+}
+
+impl EntryKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryKind::Credential => "credential",
+            EntryKind::Session => "session",
+            EntryKind::SsoPortalToken => "sso-portal-token",
+            EntryKind::Password => "password",
+        }
+    }
+}
+
+/// The description of a cached secret, without the secret itself.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEntry {
+    kind: EntryKind,
+    service: String,
+    user: String,
+    backend: CacheBackend,
+    host: Option<String>,
+    role_arn: Option<String>,
+    expiration: Option<String>,
+}
+
+impl CacheEntry {
+    /// Generates a new [`CacheEntry`] for the secret of `user` in `service`, as it is stored in
+    /// the cache.
+    pub fn new(kind: EntryKind, service: &str, user: &str, backend: CacheBackend) -> Self {
+        Self {
+            kind,
+            service: service.to_string(),
+            user: user.to_string(),
+            backend,
+            host: None,
+            role_arn: None,
+            expiration: None,
+        }
+    }
+
+    /// The Okta host or the AWS SSO portal the secret comes from.
+    pub fn with_host(mut self, host: String) -> Self {
+        self.host = Some(host);
+        self
+    }
+
+    pub fn with_role_arn(mut self, role_arn: Option<String>) -> Self {
+        self.role_arn = role_arn;
+        self
+    }
+
+    pub fn with_expiration(mut self, expiration: String) -> Self {
+        self.expiration = Some(expiration);
+        self
+    }
+
+    pub fn kind(&self) -> EntryKind {
+        self.kind
+    }
+
+    pub fn service(&self) -> String {
+        self.service.clone()
+    }
+
+    pub fn user(&self) -> String {
+        self.user.clone()
+    }
+
+    pub fn backend(&self) -> CacheBackend {
+        self.backend
+    }
+
+    pub fn host(&self) -> Option<String> {
+        self.host.clone()
+    }
+
+    pub fn role_arn(&self) -> Option<String> {
+        self.role_arn.clone()
+    }
+
+    pub fn expiration(&self) -> Option<String> {
+        self.expiration.clone()
+    }
+
+    /// The time left until the secret expires, negative once it expired. `None` for secrets
+    /// without an expiration.
+    pub fn remaining(&self) -> Option<Duration> {
+        let expiration = OffsetDateTime::parse(self.expiration.as_deref()?, &Rfc3339).ok()?;
+
+        Some(expiration - OffsetDateTime::now_utc())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining()
+            .is_some_and(|remaining| remaining <= Duration::ZERO)
+    }
+
+    fn is_same(&self, service: &str, user: &str) -> bool {
+        self.service == service && self.user == user
+    }
+}
+
+/// The list of secrets c9s has cached, because the keyring can't be searched.
+///
+/// Updates are done while holding a lock on a sidecar `.lock` file.
+pub struct CacheIndex {
+    path: PathBuf,
+}
+
+impl CacheIndex {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn from_settings(settings: &AppConfig) -> Result<Self> {
+        Ok(Self::new(settings.cache_index_file()?))
+    }
+
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let contents = fs::read_to_string(&self.path)?;
+
+        serde_json::from_str(contents.as_str())
+            .map_err(|e| Error::Cache(format!("could not read {:?}: {e}", self.path)))
+    }
+
+    /// The entry of the secret of `user` in `service`.
+    pub fn entry(&self, service: &str, user: &str) -> Result<Option<CacheEntry>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .find(|entry| entry.is_same(service, user)))
+    }
+
+    /// Adds the entry, replacing the one of the same secret.
+    pub fn record(&self, entry: CacheEntry) -> Result<()> {
+        self.update(|entries| {
+            entries.retain(|existing| !existing.is_same(&entry.service, &entry.user));
+            entries.push(entry);
+        })
+    }
+
+    pub fn remove(&self, service: &str, user: &str) -> Result<()> {
+        self.update(|entries| entries.retain(|entry| !entry.is_same(service, user)))
+    }
+
+    fn update<F>(&self, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut Vec<CacheEntry>),
+    {
+        locked(&self.path, || {
+            let mut entries = self.entries()?;
+            update(&mut entries);
+            write_atomically(
+                &self.path,
+                serde_json::to_string_pretty(&entries)?.as_bytes(),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_remove_entries() {
+        let directory = tempfile::tempdir().unwrap();
+        let index = CacheIndex::new(directory.path().join("index.json"));
+        let role_arn = "arn:aws:iam::000222111000:role/Admin";
+
+        let credential = |expiration: &str| {
+            CacheEntry::new(
+                EntryKind::Credential,
+                "c9s credential -- arn:aws:iam::000222111000:role/Admin",
+                role_arn,
+                CacheBackend::EncryptedFile,
+            )
+            .with_host(String::from("https://example.okta.com"))
+            .with_role_arn(Some(role_arn.to_string()))
+            .with_expiration(expiration.to_string())
+        };
+        let session = CacheEntry::new(
+            EntryKind::Session,
+            "c9s session -- example.okta.com",
+            "the-user",
+            CacheBackend::Keyring,
+        );
+
+        index.record(credential("2020-01-01T00:00:00Z")).unwrap();
+        index.record(session.clone()).unwrap();
+        index.record(credential("2030-01-01T00:00:00Z")).unwrap();
+        let recorded = index.entries().unwrap();
+
+        index
+            .remove("c9s session -- example.okta.com", "the-user")
+            .unwrap();
+        let removed = index.entries().unwrap();
+
+        assert_eq!(recorded, vec![session, credential("2030-01-01T00:00:00Z")]);
+        assert!(!recorded[1].is_expired());
+        assert!(credential("2020-01-01T00:00:00Z").is_expired());
+        assert_eq!(removed, vec![credential("2030-01-01T00:00:00Z")]);
+    }
+}
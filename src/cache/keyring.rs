@@ -0,0 +1,34 @@
+use crate::cache::SecretCache;
+use crate::error::{Error, Result};
+use tmuntaner_keyring::KeyringClient;
+
+/// Caches secrets in the keyring service of the operating system.
+///
+/// The keyring client can't remove entries, so deleted secrets are overwritten with an empty
+/// string, which reads as no secret.
+pub struct KeyringCache;
+
+impl SecretCache for KeyringCache {
+    fn get(&self, service: &str, user: &str) -> Result<Option<String>> {
+        let keyring = KeyringClient::new(user, service, "c9s").map_err(keyring_error)?;
+        let secret = keyring.get_password().map_err(keyring_error)?;
+
+        Ok(secret.filter(|secret| !secret.is_empty()))
+    }
+
+    fn set(&self, service: &str, user: &str, secret: &str) -> Result<()> {
+        let keyring = KeyringClient::new(user, service, "c9s").map_err(keyring_error)?;
+
+        keyring
+            .set_password(secret.to_string())
+            .map_err(keyring_error)
+    }
+
+    fn delete(&self, service: &str, user: &str) -> Result<()> {
+        self.set(service, user, "")
+    }
+}
+
+fn keyring_error(error: anyhow::Error) -> Error {
+    Error::Cache(format!("keyring: {error:#}"))
+}
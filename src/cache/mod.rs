@@ -0,0 +1,52 @@
+use crate::error::{Error, Result};
+use crate::files::{self, create_private_dir};
+use crate::settings::{AppConfig, CacheBackend};
+use std::path::Path;
+
+pub mod accounts;
+pub mod credential_key;
+pub mod encrypted_file;
+pub mod index;
+pub mod keyring;
+
+use encrypted_file::EncryptedFileCache;
+use keyring::KeyringCache;
+
+/// A store for cached secrets like Okta sessions and AWS credentials, by their service and user.
+pub trait SecretCache: Send + Sync {
+    fn get(&self, service: &str, user: &str) -> Result<Option<String>>;
+
+    fn set(&self, service: &str, user: &str, secret: &str) -> Result<()>;
+
+    fn delete(&self, service: &str, user: &str) -> Result<()>;
+}
+
+/// The cache selected in the settings, `None` when caching is disabled.
+pub fn from_settings(settings: &AppConfig) -> Result<Option<Box<dyn SecretCache>>> {
+    match settings.cache_backend() {
+        CacheBackend::Keyring if !settings.keyring_enabled() => Ok(None),
+        backend => Ok(Some(open(backend, settings)?)),
+    }
+}
+
+/// The cache of the backend, even if it isn't the selected one.
+pub fn open(backend: CacheBackend, settings: &AppConfig) -> Result<Box<dyn SecretCache>> {
+    match backend {
+        CacheBackend::Keyring => Ok(Box::new(KeyringCache)),
+        CacheBackend::EncryptedFile => Ok(Box::new(EncryptedFileCache::from_settings(settings)?)),
+    }
+}
+
+/// Runs `update` while holding the lock of `path`, creating the private directory of the cache
+/// first.
+fn locked<T, F>(path: &Path, update: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    let directory = path
+        .parent()
+        .ok_or_else(|| Error::Cache(format!("{path:?} has no parent directory")))?;
+    create_private_dir(directory)?;
+
+    files::locked(path, update)
+}
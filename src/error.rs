@@ -0,0 +1,71 @@
+use thiserror::Error;
+
+/// The errors returned by the c9s library.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Okta rejected the username or the password.
+    #[error("invalid username or password")]
+    InvalidCredentials,
+    /// The user or Okta rejected the MFA challenge.
+    #[error("MFA challenge was rejected")]
+    MfaRejected,
+    /// The user picked another number than the one of the Okta Verify number challenge.
+    #[error("MFA challenge was rejected because the selected number didn't match")]
+    MfaNumberMismatch,
+    /// The one-time code of an SMS, call or email factor was wrong too often.
+    #[error("MFA challenge failed, the code was wrong too often")]
+    MfaInvalidPasscode,
+    /// The MFA challenge wasn't answered in time.
+    #[error("MFA challenge timed out")]
+    MfaTimeout,
+    /// The selected MFA factor isn't enrolled for the user.
+    #[error("MFA factor not found")]
+    FactorNotFound,
+    /// Signing the WebAuthn challenge with a security key failed.
+    #[error("WebAuthn signing failed: {0}")]
+    WebAuthn(String),
+    /// A server answered with an unexpected HTTP status.
+    #[error("unexpected HTTP status {code} from {url}")]
+    HttpStatus { code: u16, url: String },
+    /// The SAML response couldn't be found or parsed.
+    #[error("could not parse SAML response: {0}")]
+    SamlParse(String),
+    /// STS failed to generate credentials.
+    #[error("STS request failed: {0}")]
+    StsFailure(String),
+    /// The requested role isn't available to the user.
+    #[error("could not find role {0}")]
+    RoleNotFound(String),
+    /// A role ARN couldn't be parsed.
+    #[error("invalid ARN {0}")]
+    InvalidArn(String),
+    /// The settings or the arguments are invalid.
+    #[error("invalid configuration: {0}")]
+    Config(String),
+    /// A server answered with a response c9s doesn't understand.
+    #[error("unexpected response: {0}")]
+    UnexpectedResponse(String),
+    /// The user's input couldn't be understood.
+    #[error("invalid input: {0}")]
+    UserInput(String),
+    /// The cache couldn't be read or written, e.g. because it was tampered with.
+    #[error("could not use the cache: {0}")]
+    Cache(String),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+}
+
+impl Error {
+    /// Whether a server rejected the credentials or the token of a request.
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(self, Error::HttpStatus { code: 401, .. })
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,73 @@
+use crate::error::{Error, Result};
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Runs `update` while holding a lock on the sidecar `.<name>.lock` file of `path`, so that
+/// concurrent runs of c9s don't lose each other's changes. The directory of `path` has to exist.
+pub(crate) fn locked<T, F>(path: &Path, update: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    let lock = File::create(sibling(path, "lock")?)?;
+    lock.lock_exclusive()?;
+    let result = update();
+    lock.unlock()?;
+
+    result
+}
+
+/// Replaces the file with `contents` through a temporary file, so that readers never see a
+/// partly written file. A new file is only readable by the user, an existing one keeps its
+/// permissions.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let temporary = sibling(path, "c9s-tmp")?;
+    write_private(&temporary, contents)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(&temporary, metadata.permissions())?;
+    }
+    fs::rename(temporary, path)?;
+
+    Ok(())
+}
+
+/// Creates the directory and its parents, only accessible by the user.
+pub(crate) fn create_private_dir(directory: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+
+    Ok(builder.create(directory)?)
+}
+
+/// Writes a file only the user can read.
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// The hidden file `.<name>.<extension>` next to `path`.
+fn sibling(path: &Path, extension: &str) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::Config(format!("{path:?} is not a file")))?
+        .to_string_lossy();
+
+    Ok(path.with_file_name(format!(".{file_name}.{extension}")))
+}
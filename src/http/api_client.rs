@@ -1,4 +1,4 @@
-use anyhow::{anyhow, Result};
+use crate::error::{Error, Result};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT};
 use reqwest::{Client, Response};
 use serde_json::Value;
@@ -24,19 +24,25 @@ impl ApiClient {
     }
 
     pub async fn post_json(&self, uri: &str, json: &Value) -> Result<Response> {
+        self.post_json_with_headers(uri, json, None).await
+    }
+
+    pub async fn post_json_with_headers(
+        &self,
+        uri: &str,
+        json: &Value,
+        headers: Option<HashMap<String, String>>,
+    ) -> Result<Response> {
+        let header_map = ApiClient::header_map(AcceptType::Json, headers)?;
         let response = self
             .http_client
             .post(uri)
             .json(json)
-            .header(ACCEPT, HeaderValue::from_static("application/json"))
+            .headers(header_map)
             .send()
             .await?;
 
-        if response.status() != reqwest::StatusCode::OK {
-            return Err(anyhow!("non 200 response"));
-        }
-
-        Ok(response)
+        ApiClient::check_status(response)
     }
 
     pub async fn post_form(
@@ -78,6 +84,12 @@ impl ApiClient {
                 )
                 .await
             {
+                // client errors won't go away by retrying, apart from rate limits
+                Err(Error::HttpStatus { code, url })
+                    if (400..500).contains(&code) && code != 429 =>
+                {
+                    return Err(Error::HttpStatus { code, url });
+                }
                 Err(e) => {
                     if retries > 0 {
                         retries -= 1;
@@ -101,7 +113,6 @@ impl ApiClient {
         headers: Option<HashMap<String, String>>,
         accept_type: AcceptType,
     ) -> Result<Response> {
-        let accept_header = ApiClient::accept_header(accept_type);
         let mut url = Url::parse(url.as_str())?;
 
         for (key, value) in &params.unwrap_or_default() {
@@ -109,24 +120,46 @@ impl ApiClient {
                 .append_pair(key.as_str(), value.as_str());
         }
 
-        let mut header_map = HeaderMap::new();
-        header_map.insert(ACCEPT, accept_header);
-        for (key, value) in &headers.unwrap_or_default() {
-            let header_value = HeaderValue::from_str(value.as_str())?;
-            let header_key = HeaderName::from_lowercase(key.as_bytes())?;
-            header_map.insert(header_key, header_value);
-        }
-
+        let header_map = ApiClient::header_map(accept_type, headers)?;
         let request = self.http_client.get(url).headers(header_map);
-        let response = request.send().await?;
+        // the query may hold secrets, like the session of the console's federation endpoint
+        let response = request.send().await.map_err(reqwest::Error::without_url)?;
 
+        ApiClient::check_status(response)
+    }
+
+    /// Turns every response other than 200 into an [`Error::HttpStatus`], the URL of the error
+    /// leaves out the query.
+    fn check_status(response: Response) -> Result<Response> {
         if response.status() != reqwest::StatusCode::OK {
-            return Err(anyhow!("non 200 response"));
+            let mut url = response.url().clone();
+            url.set_query(None);
+            return Err(Error::HttpStatus {
+                code: response.status().as_u16(),
+                url: url.to_string(),
+            });
         }
 
         Ok(response)
     }
 
+    fn header_map(
+        accept_type: AcceptType,
+        headers: Option<HashMap<String, String>>,
+    ) -> Result<HeaderMap> {
+        let mut header_map = HeaderMap::new();
+        header_map.insert(ACCEPT, ApiClient::accept_header(accept_type));
+        for (key, value) in &headers.unwrap_or_default() {
+            let header_value = HeaderValue::from_str(value.as_str())
+                .map_err(|e| Error::Config(format!("invalid header value for {key}: {e}")))?;
+            let header_key = HeaderName::from_lowercase(key.as_bytes())
+                .map_err(|e| Error::Config(format!("invalid header name {key}: {e}")))?;
+            header_map.insert(header_key, header_value);
+        }
+
+        Ok(header_map)
+    }
+
     fn accept_header(accept_type: AcceptType) -> HeaderValue {
         match accept_type {
             AcceptType::Html => {
@@ -1,4 +1,9 @@
 pub mod aws;
+pub mod cache;
+mod error;
+mod files;
 mod http;
 pub mod okta;
 pub mod settings;
+
+pub use error::{Error, Result};
@@ -1,3 +1,4 @@
+use crate::okta::okta_client::MfaSelection;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -37,6 +38,7 @@ pub struct Response {
     embedded: Option<Embedded>,
     status: Option<TransactionState>,
     factor_result: Option<FactorResult>,
+    factor_result_message: Option<String>,
     #[serde(rename = "_links")]
     links: Option<HashMap<String, Links>>,
 }
@@ -98,6 +100,35 @@ impl Response {
         self.links.as_ref()?.get("next")?.link()
     }
 
+    /// Tries to return the link which sends the one-time code of a factor again.
+    pub fn resend(&self) -> Option<String> {
+        self.links.as_ref()?.get("resend")?.link()
+    }
+
+    /// Tries to return the factor which is challenged.
+    pub fn factor(&self) -> Option<FactorType> {
+        let factor = self.embedded.as_ref()?.factor.clone()?;
+
+        serde_json::from_value(factor).ok()
+    }
+
+    /// Tries to return the number the user has to pick in Okta Verify for a push with number
+    /// matching.
+    ///
+    /// <https://developer.okta.com/docs/reference/api/authn/#response-example-waiting-for-3-number-verification-challenge-response>
+    pub fn correct_answer(&self) -> Option<u32> {
+        let factor = self.embedded.as_ref()?.factor.clone()?;
+        let factor: ChallengedFactor = serde_json::from_value(factor).ok()?;
+
+        factor.embedded?.challenge?.correct_answer
+    }
+
+    /// Whether a rejected push was rejected because another number was picked. Okta explains
+    /// those rejections with a factor result message, a denial by the user comes without one.
+    pub fn is_number_mismatch(&self) -> bool {
+        self.factor_result_message.is_some()
+    }
+
     /// Tries to return the MFA challenge.
     pub fn challenge(&self) -> Option<String> {
         Some(
@@ -130,6 +161,27 @@ struct Embedded {
 
     #[serde(default)]
     challenge: Option<Challenge>,
+
+    /// The challenged factor, parsed on demand so that its shape can't fail the response.
+    #[serde(default)]
+    factor: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChallengedFactor {
+    #[serde(rename = "_embedded")]
+    embedded: Option<ChallengedFactorEmbedded>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChallengedFactorEmbedded {
+    challenge: Option<NumberChallenge>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NumberChallenge {
+    correct_answer: Option<u32>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -168,6 +220,8 @@ pub struct Profile {
     name: Option<String>,
     platform: Option<String>,
     version: Option<String>,
+    phone_number: Option<String>,
+    email: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -198,6 +252,39 @@ pub enum FactorType {
         #[serde(rename = "_links")]
         links: Option<HashMap<String, Links>>,
     },
+    /// A one-time code sent as a text message.
+    Sms {
+        provider: Option<String>,
+        profile: Option<Profile>,
+
+        #[serde(rename = "_links")]
+        links: Option<HashMap<String, Links>>,
+    },
+    /// A one-time code read out in a voice call.
+    Call {
+        provider: Option<String>,
+        profile: Option<Profile>,
+
+        #[serde(rename = "_links")]
+        links: Option<HashMap<String, Links>>,
+    },
+    /// A one-time code sent by email.
+    Email {
+        provider: Option<String>,
+        profile: Option<Profile>,
+
+        #[serde(rename = "_links")]
+        links: Option<HashMap<String, Links>>,
+    },
+    /// A one-time password of a hardware token like a YubiKey.
+    #[serde(rename = "token:hardware")]
+    Hardware {
+        provider: Option<String>,
+        profile: Option<Profile>,
+
+        #[serde(rename = "_links")]
+        links: Option<HashMap<String, Links>>,
+    },
     #[serde(other)]
     Unimplemented,
 }
@@ -208,10 +295,44 @@ impl FactorType {
             FactorType::Push { ref provider, .. } => Some(provider.clone()),
             FactorType::WebAuthn { .. } => None,
             FactorType::Totp { ref provider, .. } => Some(provider.clone()),
+            FactorType::Sms { ref provider, .. }
+            | FactorType::Call { ref provider, .. }
+            | FactorType::Email { ref provider, .. }
+            | FactorType::Hardware { ref provider, .. } => provider.clone(),
             FactorType::Unimplemented => None,
         }
     }
 
+    /// Whether the factor sends a one-time code, which is requested before it can be verified.
+    pub fn sends_passcode(&self) -> bool {
+        matches!(
+            self,
+            FactorType::Sms { .. } | FactorType::Call { .. } | FactorType::Email { .. }
+        )
+    }
+
+    /// Whether the factor is the one the user selected, TOTP factors also need to match the
+    /// provider.
+    pub fn is_selected_by(&self, mfa: MfaSelection, mfa_provider: Option<&str>) -> bool {
+        match (mfa, self) {
+            (MfaSelection::Totp, FactorType::Totp { .. }) => {
+                match (self.provider(), mfa_provider) {
+                    (Some(provider), Some(mfa_provider)) => {
+                        provider.to_lowercase() == mfa_provider.to_lowercase()
+                    }
+                    _ => false,
+                }
+            }
+            (MfaSelection::OktaPush, FactorType::Push { .. })
+            | (MfaSelection::WebAuthn, FactorType::WebAuthn { .. })
+            | (MfaSelection::Sms, FactorType::Sms { .. })
+            | (MfaSelection::Call, FactorType::Call { .. })
+            | (MfaSelection::Email, FactorType::Email { .. })
+            | (MfaSelection::HardwareToken, FactorType::Hardware { .. }) => true,
+            _ => false,
+        }
+    }
+
     pub fn human_friendly_name(&self) -> String {
         match self {
             FactorType::Push { .. } => String::from("Okta Push"),
@@ -219,6 +340,26 @@ impl FactorType {
             FactorType::Totp { ref provider, .. } => {
                 format!("TOTP ({provider})")
             }
+            FactorType::Sms { ref profile, .. } => {
+                match profile_value(profile, |p| &p.phone_number) {
+                    Some(phone_number) => format!("SMS ({phone_number})"),
+                    None => String::from("SMS"),
+                }
+            }
+            FactorType::Call { ref profile, .. } => {
+                match profile_value(profile, |p| &p.phone_number) {
+                    Some(phone_number) => format!("Voice Call ({phone_number})"),
+                    None => String::from("Voice Call"),
+                }
+            }
+            FactorType::Email { ref profile, .. } => match profile_value(profile, |p| &p.email) {
+                Some(email) => format!("Email ({email})"),
+                None => String::from("Email"),
+            },
+            FactorType::Hardware { ref provider, .. } => match provider {
+                Some(provider) => format!("Hardware Token ({provider})"),
+                None => String::from("Hardware Token"),
+            },
             FactorType::Unimplemented => String::from("Unimplemented"),
         }
     }
@@ -228,6 +369,10 @@ impl FactorType {
             FactorType::WebAuthn { ref links, .. } => links.as_ref()?.get("next")?.link(),
             FactorType::Push { ref links, .. } => links.as_ref()?.get("verify")?.link(),
             FactorType::Totp { ref links, .. } => links.as_ref()?.get("verify")?.link(),
+            FactorType::Sms { ref links, .. }
+            | FactorType::Call { ref links, .. }
+            | FactorType::Email { ref links, .. }
+            | FactorType::Hardware { ref links, .. } => links.as_ref()?.get("verify")?.link(),
             _ => None,
         }
     }
@@ -248,3 +393,88 @@ impl FactorType {
         }
     }
 }
+
+fn profile_value<F>(profile: &Option<Profile>, field: F) -> Option<String>
+where
+    F: Fn(&Profile) -> &Option<String>,
+{
+    field(profile.as_ref()?).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passcode_factors() {
+        let required: Response = serde_json::from_value(serde_json::json!({
+            "stateToken": "TheToken",
+            "status": "MFA_REQUIRED",
+            "_embedded": { "factors": [
+                {
+                    "id": "sms1", "factorType": "sms", "provider": "OKTA",
+                    "profile": { "phoneNumber": "+1 XXX-XXX-4601" },
+                    "_links": { "verify": { "href": "https://example.okta.com/api/v1/authn/factors/sms1/verify" } },
+                },
+                {
+                    "id": "call1", "factorType": "call", "provider": "OKTA",
+                    "profile": { "phoneNumber": "+1 XXX-XXX-4601" },
+                    "_links": { "verify": { "href": "https://example.okta.com/api/v1/authn/factors/call1/verify" } },
+                },
+                {
+                    "id": "email1", "factorType": "email", "provider": "OKTA",
+                    "profile": { "email": "t...r@example.com" },
+                    "_links": { "verify": { "href": "https://example.okta.com/api/v1/authn/factors/email1/verify" } },
+                },
+                {
+                    "id": "ykf1", "factorType": "token:hardware", "provider": "YUBICO",
+                    "_links": { "verify": { "href": "https://example.okta.com/api/v1/authn/factors/ykf1/verify" } },
+                },
+                { "id": "q1", "factorType": "question", "provider": "OKTA" },
+            ]},
+        }))
+        .unwrap();
+        let factors = required.factors().unwrap();
+        let names: Vec<String> = factors
+            .iter()
+            .map(FactorType::human_friendly_name)
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "SMS (+1 XXX-XXX-4601)",
+                "Voice Call (+1 XXX-XXX-4601)",
+                "Email (t...r@example.com)",
+                "Hardware Token (YUBICO)",
+            ]
+        );
+        assert_eq!(
+            factors[3].get_verification_url().unwrap(),
+            "https://example.okta.com/api/v1/authn/factors/ykf1/verify"
+        );
+        assert!(factors[0].sends_passcode());
+        assert!(!factors[3].sends_passcode());
+        assert!(factors[1].is_selected_by(MfaSelection::Call, None));
+        assert!(!factors[1].is_selected_by(MfaSelection::Sms, None));
+        assert!(factors[3].is_selected_by(MfaSelection::HardwareToken, None));
+
+        let challenge: Response = serde_json::from_value(serde_json::json!({
+            "stateToken": "TheToken",
+            "status": "MFA_CHALLENGE",
+            "factorResult": "CHALLENGE",
+            "_embedded": { "factor": { "id": "sms1", "factorType": "sms", "provider": "OKTA" } },
+            "_links": {
+                "next": { "name": "verify", "href": "https://example.okta.com/api/v1/authn/factors/sms1/verify" },
+                "resend": [{ "name": "sms", "href": "https://example.okta.com/api/v1/authn/factors/sms1/verify/resend" }],
+            },
+        }))
+        .unwrap();
+
+        assert!(challenge.factor().unwrap().sends_passcode());
+        assert_eq!(
+            challenge.resend().unwrap(),
+            "https://example.okta.com/api/v1/authn/factors/sms1/verify/resend"
+        );
+    }
+}
@@ -1,15 +1,19 @@
 use crate::okta::authenticator::api_responses::{
     FactorResult, FactorType, Response, TransactionState,
 };
-use std::io::{self, BufRead, Write};
+use crate::okta::authenticator::{
+    ask_user, ask_user_to_select, show_number_challenge, sign_webauthn,
+};
 use std::{thread, time};
 
+use crate::error::{Error, Result};
 use crate::http::api_client::ApiClient;
 use crate::okta::okta_client::MfaSelection;
-use anyhow::{anyhow, Result};
-use tmuntaner_webauthn::WebauthnClient;
 use url::Url;
 
+/// How often a wrong SMS, call or email code may be entered before giving up.
+const PASSCODE_ATTEMPTS: u32 = 3;
+
 /// Goes through the Okta Authentication state machine to finally generate a session token.
 ///
 /// See <https://developer.okta.com/docs/reference/api/authn/#transaction-state> for more details
@@ -17,6 +21,8 @@ use url::Url;
 pub struct AuthenticatorClient {
     client: ApiClient,
     enable_desktop_notifications: bool,
+    /// Reads the one-time codes of SMS, calls and email.
+    ask_passcode: fn(&str) -> Result<String>,
 }
 
 impl AuthenticatorClient {
@@ -26,6 +32,7 @@ impl AuthenticatorClient {
         Ok(AuthenticatorClient {
             client,
             enable_desktop_notifications,
+            ask_passcode: ask_user,
         })
     }
 
@@ -42,11 +49,14 @@ impl AuthenticatorClient {
             .try_authorize(app_url.clone(), username, password)
             .await?;
 
+        // the number of a number challenge, once it was shown to the user
+        let mut shown_answer = None;
+
         // loop over the mutated response until we reach a success state or an error.
         loop {
             match response
                 .status()
-                .ok_or_else(|| anyhow!("could not get status"))?
+                .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get status")))?
             {
                 TransactionState::MfaRequired => {
                     response = self
@@ -54,34 +64,48 @@ impl AuthenticatorClient {
                         .await?
                 }
                 TransactionState::MfaChallenge => {
-                    let result = response
-                        .factor_result()
-                        .ok_or_else(|| anyhow!("could not get factor result"))?;
+                    let result = response.factor_result().ok_or_else(|| {
+                        Error::UnexpectedResponse(String::from("could not get factor result"))
+                    })?;
 
                     match result {
-                        FactorResult::Challenge => {
-                            response = self.mfa_challenge(&response, app_url.clone()).await?
-                        }
+                        FactorResult::Challenge => match response.factor() {
+                            Some(factor) if factor.sends_passcode() => {
+                                response = self.mfa_passcode(&response, &factor).await?
+                            }
+                            _ => response = self.mfa_challenge(&response, app_url.clone()).await?,
+                        },
                         FactorResult::Waiting => {
-                            response = self.mfa_challenge_waiting(&response).await?
+                            response = self
+                                .mfa_challenge_waiting(&response, &mut shown_answer)
+                                .await?
                         }
-                        FactorResult::Rejected => {
-                            return Err(anyhow!("MFA Challenge was rejected-"))
+                        FactorResult::Rejected
+                            if shown_answer.is_some() && response.is_number_mismatch() =>
+                        {
+                            return Err(Error::MfaNumberMismatch)
                         }
-                        FactorResult::Timeout => return Err(anyhow!("MFA Challenge timed out")),
+                        FactorResult::Rejected => return Err(Error::MfaRejected),
+                        FactorResult::Timeout => return Err(Error::MfaTimeout),
                         FactorResult::Unimplemented => {
-                            return Err(anyhow!("unimplemented MFA factor"))
+                            return Err(Error::UnexpectedResponse(String::from(
+                                "unimplemented MFA factor result",
+                            )))
                         }
                     }
                 }
                 TransactionState::Success => {
-                    let session_token = response
-                        .session_token()
-                        .ok_or_else(|| anyhow!("could not get session token"))?;
+                    let session_token = response.session_token().ok_or_else(|| {
+                        Error::UnexpectedResponse(String::from("could not get session token"))
+                    })?;
 
                     return Ok(session_token);
                 }
-                TransactionState::Unimplemented => return Err(anyhow!("unimplemented")),
+                TransactionState::Unimplemented => {
+                    return Err(Error::UnexpectedResponse(String::from(
+                        "unimplemented transaction state",
+                    )))
+                }
             }
         }
     }
@@ -107,7 +131,10 @@ impl AuthenticatorClient {
             .client
             .post_json(url.as_str(), json)
             .await
-            .map_err(|e| anyhow!(e))?;
+            .map_err(|e| match e {
+                Error::HttpStatus { code: 401, .. } => Error::InvalidCredentials,
+                e => e,
+            })?;
 
         let body = response.text().await?;
         let response: Response = serde_json::from_str(body.as_str())?;
@@ -128,17 +155,17 @@ impl AuthenticatorClient {
     ) -> Result<Response> {
         let state_token = response
             .state_token()
-            .ok_or_else(|| anyhow!("could not get state token"))?;
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get state token")))?;
 
         let factors = response
             .factors()
-            .ok_or_else(|| anyhow!("could not get factors"))?;
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get factors")))?;
 
         let factor = self.selected_mfa_factor(factors, mfa, mfa_provider)?;
 
-        let url = factor
-            .get_verification_url()
-            .ok_or_else(|| anyhow!("could not get verification url"))?;
+        let url = factor.get_verification_url().ok_or_else(|| {
+            Error::UnexpectedResponse(String::from("could not get verification url"))
+        })?;
 
         let json = match factor {
             FactorType::Totp { .. } => {
@@ -149,6 +176,15 @@ impl AuthenticatorClient {
                     "stateToken": state_token,
                 })
             }
+            FactorType::Hardware { .. } => {
+                let passcode = ask_user("Hardware Token Code: ")?;
+
+                serde_json::json!({
+                    "passCode": passcode,
+                    "stateToken": state_token,
+                })
+            }
+            // factors sending a code are verified without one first, which sends the code
             _ => {
                 serde_json::json!({
                     "stateToken": state_token,
@@ -172,35 +208,25 @@ impl AuthenticatorClient {
     async fn mfa_challenge(&self, response: &Response, app_url: String) -> Result<Response> {
         let factors = response
             .factors()
-            .ok_or_else(|| anyhow!("could not get mfa factors"))?;
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get mfa factors")))?;
         let state_token = response
             .state_token()
-            .ok_or_else(|| anyhow!("could not get state token"))?;
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get state token")))?;
         let challenge = response
             .challenge()
-            .ok_or_else(|| anyhow!("could not get challenge"))?;
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get challenge")))?;
 
         let credential_ids: Vec<String> = factors
             .iter()
             .filter_map(|factor| factor.get_credential_id())
             .collect();
 
-        let origin = Url::parse(app_url.as_str())?;
-        if origin.scheme() != "https" {
-            return Err(anyhow!("U2F request should be https"));
-        }
-
-        let host = origin
-            .host()
-            .ok_or_else(|| anyhow!("couldn't get host from url"))?
-            .to_string();
-
-        let mut webauthn_client = WebauthnClient::new();
-        if self.enable_desktop_notifications {
-            webauthn_client.add_desktop_notification_notifier();
-        }
-        webauthn_client.add_progress_bar_notifier();
-        let u2f_response = webauthn_client.sign(challenge, host, credential_ids)?;
+        let u2f_response = sign_webauthn(
+            app_url.as_str(),
+            challenge,
+            credential_ids,
+            self.enable_desktop_notifications,
+        )?;
         let json = &serde_json::json!({
             "stateToken": state_token,
             "clientData": u2f_response.client_data,
@@ -210,7 +236,7 @@ impl AuthenticatorClient {
 
         let url = response
             .next()
-            .ok_or_else(|| anyhow!("could not get next page"))?;
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get next page")))?;
 
         let response = self.client.post_json(url.as_str(), json).await?;
         let body = response.text().await?;
@@ -219,17 +245,87 @@ impl AuthenticatorClient {
         Ok(response)
     }
 
+    /// Verifies the code an SMS, call or email factor sent, or sends it again when the user
+    /// leaves the code empty.
+    ///
+    /// <https://developer.okta.com/docs/reference/api/authn/#verify-sms-factor>
+    async fn mfa_passcode(&self, response: &Response, factor: &FactorType) -> Result<Response> {
+        let state_token = response
+            .state_token()
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get state token")))?;
+
+        let prompt = format!(
+            "{} Code (leave empty to resend): ",
+            factor.human_friendly_name()
+        );
+        let mut attempts = PASSCODE_ATTEMPTS;
+        loop {
+            let passcode = (self.ask_passcode)(prompt.as_str())?;
+            let (url, json) = match passcode.is_empty() {
+                true => (
+                    response.resend().ok_or_else(|| {
+                        Error::UnexpectedResponse(String::from("could not get resend link"))
+                    })?,
+                    serde_json::json!({
+                        "stateToken": state_token,
+                    }),
+                ),
+                false => (
+                    response.next().ok_or_else(|| {
+                        Error::UnexpectedResponse(String::from("could not get next page"))
+                    })?,
+                    serde_json::json!({
+                        "passCode": passcode,
+                        "stateToken": state_token,
+                    }),
+                ),
+            };
+
+            match self.client.post_json(url.as_str(), &json).await {
+                Ok(response) => {
+                    let body = response.text().await?;
+                    return Ok(serde_json::from_str(body.as_str())?);
+                }
+                // Okta only sends a new code every 30 seconds
+                Err(Error::HttpStatus { code: 429, .. }) => {
+                    eprintln!("A code was sent recently, wait 30 seconds before resending it.");
+                }
+                Err(Error::HttpStatus { code: 403, .. }) if !passcode.is_empty() => {
+                    attempts -= 1;
+                    if attempts == 0 {
+                        return Err(Error::MfaInvalidPasscode);
+                    }
+                    eprintln!("The code is invalid, please try again.");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
     /// Polls during an MFA Challenge
     ///
     /// <https://developer.okta.com/docs/reference/api/authn/#response-example-waiting-for-3-number-verification-challenge-response>
-    async fn mfa_challenge_waiting(&self, response: &Response) -> Result<Response> {
+    ///
+    /// With number matching, the number the user has to pick is shown once.
+    async fn mfa_challenge_waiting(
+        &self,
+        response: &Response,
+        shown_answer: &mut Option<u32>,
+    ) -> Result<Response> {
+        if let Some(correct_answer) = response.correct_answer() {
+            if *shown_answer != Some(correct_answer) {
+                show_number_challenge(correct_answer, self.enable_desktop_notifications);
+                *shown_answer = Some(correct_answer);
+            }
+        }
+
         let state_token = response
             .state_token()
-            .ok_or_else(|| anyhow!("could not get state token"))?;
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get state token")))?;
 
         let url = response
             .next()
-            .ok_or_else(|| anyhow!("could not get next page"))?;
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get next page")))?;
 
         let json = &serde_json::json!({
            "stateToken": state_token,
@@ -268,95 +364,152 @@ impl AuthenticatorClient {
             .collect();
 
         match mfa {
-            Some(mfa) => match mfa {
-                MfaSelection::Totp => {
-                    let factors: Vec<FactorType> = factors
-                        .into_iter()
-                        .filter(|factor| matches!(factor, FactorType::Totp { .. }))
-                        .filter(|factor| match &mfa_provider {
-                            Some(mfa_provider) => match factor.provider() {
-                                Some(factor_provider) => {
-                                    factor_provider.to_lowercase() == mfa_provider.to_lowercase()
-                                }
-                                None => false,
-                            },
-                            None => false,
-                        })
-                        .collect();
-                    let factor = factors
-                        .first()
-                        .ok_or_else(|| anyhow!("MFA Factor not found"))?
-                        .clone();
-
-                    Ok(factor)
-                }
-                MfaSelection::OktaPush => {
-                    let factors: Vec<FactorType> = factors
-                        .into_iter()
-                        .filter(|factor| matches!(factor, FactorType::Push { .. }))
-                        .collect();
-                    let factor = factors
-                        .first()
-                        .ok_or_else(|| anyhow!("MFA Factor not found"))?
-                        .clone();
-
-                    Ok(factor)
-                }
-                MfaSelection::WebAuthn => {
-                    let factors: Vec<FactorType> = factors
-                        .into_iter()
-                        .filter(|factor| matches!(factor, FactorType::WebAuthn { .. }))
-                        .collect();
-                    let factor = factors
-                        .first()
-                        .ok_or_else(|| anyhow!("MFA Factor not found"))?
-                        .clone();
-
-                    Ok(factor)
-                }
-                _ => Err(anyhow!("MFA Factor not found")),
-            },
+            Some(mfa) => factors
+                .into_iter()
+                .find(|factor| factor.is_selected_by(mfa, mfa_provider.as_deref()))
+                .ok_or(Error::FactorNotFound),
             None => self.ask_user_for_mfa_factor(factors),
         }
     }
 
     fn ask_user_for_mfa_factor(&self, factors: Vec<FactorType>) -> Result<FactorType> {
-        let min: usize = 0;
-        let max: usize = factors.len();
+        let names: Vec<String> = factors
+            .iter()
+            .map(|factor| factor.human_friendly_name())
+            .collect();
+        let selection = ask_user_to_select("Please select a MFA Factor Type:", &names)?;
 
-        eprintln!("Please select a MFA Factor Type:");
-        for (i, factor) in factors.iter().enumerate() {
-            eprintln!("( {} ) {}", i, factor.human_friendly_name());
-        }
+        Ok(factors[selection].clone())
+    }
 
-        eprint!("Factor Type? ({min} - {max}) ");
-        let _ = io::stdout().flush();
-        let mut buffer = String::new();
-        io::stdin().lock().read_line(&mut buffer)?;
-        // remove \n on unix or \r\n on windows
-        let len = buffer.trim_end_matches(&['\r', '\n'][..]).len();
-        buffer.truncate(len);
-
-        let selection: usize = buffer
-            .parse()
-            .map_err(|_| anyhow!("failed to parse your selection"))?;
-        if selection > max {
-            return Err(anyhow!("you've selected an invalid Factor Type"));
-        }
-        let factor = factors.get(selection).ok_or_else(|| anyhow!(""))?;
+    fn ask_user_for_totp(&self) -> Result<String> {
+        ask_user("TOTP Code: ")
+    }
+}
 
-        Ok(factor.clone())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    /// Runs a push with the number 92, which Okta answers with the rejection.
+    async fn reject_number_challenge(rejection: serde_json::Value) -> Result<String> {
+        let server = MockServer::start();
+        let waiting = serde_json::json!({
+            "stateToken": "TheToken",
+            "status": "MFA_CHALLENGE",
+            "factorResult": "WAITING",
+            "_embedded": { "factor": {
+                "id": "opf1",
+                "factorType": "push",
+                "provider": "OKTA",
+                "_embedded": { "challenge": { "correctAnswer": 92 } },
+            }},
+            "_links": { "next": {
+                "name": "poll",
+                "href": server.url("/api/v1/authn/factors/opf1/verify"),
+            }},
+        });
+        server.mock(|when, then| {
+            when.method("POST").path("/api/v1/authn");
+            then.status(200).json_body(waiting.clone());
+        });
+        let poll_mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/api/v1/authn/factors/opf1/verify")
+                .json_body(serde_json::json!({ "stateToken": "TheToken" }));
+            then.status(200).json_body(rejection);
+        });
+
+        let response: Response = serde_json::from_value(waiting).unwrap();
+        let client = AuthenticatorClient::new(false).unwrap();
+        let result = client
+            .run(
+                server.url("/home/amazon_aws/0oa/272"),
+                String::from("the-user"),
+                String::from("the-password"),
+                None,
+                None,
+            )
+            .await;
+
+        poll_mock.assert();
+        assert_eq!(response.correct_answer(), Some(92));
+        result
     }
 
-    fn ask_user_for_totp(&self) -> Result<String> {
-        eprint!("TOTP Code: ");
-        let _ = io::stdout().flush();
-        let mut buffer = String::new();
-        io::stdin().lock().read_line(&mut buffer)?;
-        // remove \n on unix or \r\n on windows
-        let len = buffer.trim_end_matches(&['\r', '\n'][..]).len();
-        buffer.truncate(len);
-
-        Ok(buffer)
+    #[tokio::test]
+    async fn test_number_challenge_mismatch() {
+        let result = reject_number_challenge(serde_json::json!({
+            "stateToken": "TheToken",
+            "status": "MFA_CHALLENGE",
+            "factorResult": "REJECTED",
+            "factorResultMessage": "The number selected did not match",
+        }))
+        .await;
+
+        assert!(matches!(result, Err(Error::MfaNumberMismatch)));
+    }
+
+    #[tokio::test]
+    async fn test_number_challenge_denied() {
+        let result = reject_number_challenge(serde_json::json!({
+            "stateToken": "TheToken",
+            "status": "MFA_CHALLENGE",
+            "factorResult": "REJECTED",
+        }))
+        .await;
+
+        assert!(matches!(result, Err(Error::MfaRejected)));
+    }
+
+    #[tokio::test]
+    async fn test_passcode_attempts_exhausted() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method("POST").path("/api/v1/authn");
+            then.status(200).json_body(serde_json::json!({
+                "stateToken": "TheToken",
+                "status": "MFA_CHALLENGE",
+                "factorResult": "CHALLENGE",
+                "_embedded": { "factor": { "id": "sms1", "factorType": "sms" } },
+                "_links": {
+                    "next": {
+                        "name": "verify",
+                        "href": server.url("/api/v1/authn/factors/sms1/verify"),
+                    },
+                    "resend": {
+                        "name": "sms",
+                        "href": server.url("/api/v1/authn/factors/sms1/verify/resend"),
+                    },
+                },
+            }));
+        });
+        let verify_mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/api/v1/authn/factors/sms1/verify")
+                .json_body(serde_json::json!({ "passCode": "000000", "stateToken": "TheToken" }));
+            then.status(403).json_body(serde_json::json!({
+                "errorCode": "E0000068",
+                "errorSummary": "Invalid Passcode/Answer",
+            }));
+        });
+
+        let client = AuthenticatorClient {
+            ask_passcode: |_| Ok(String::from("000000")),
+            ..AuthenticatorClient::new(false).unwrap()
+        };
+        let result = client
+            .run(
+                server.url("/home/amazon_aws/0oa/272"),
+                String::from("the-user"),
+                String::from("the-password"),
+                None,
+                None,
+            )
+            .await;
+
+        verify_mock.assert_hits(PASSCODE_ATTEMPTS as usize);
+        assert!(matches!(result, Err(Error::MfaInvalidPasscode)));
     }
 }
@@ -0,0 +1,580 @@
+use crate::error::{Error, Result};
+use crate::http::api_client::{AcceptType, ApiClient};
+use crate::okta::authenticator::idx_responses::{
+    AuthenticatorOption, IdxResponse, Message, Organization, Remediation,
+};
+use crate::okta::authenticator::{
+    ask_user, ask_user_to_select, show_number_challenge, sign_webauthn,
+};
+use crate::okta::okta_client::MfaSelection;
+use crate::okta::session::{api_url, OktaSession};
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The media type of the Identity Engine API, in the version c9s understands.
+const ION_JSON: &str = "application/ion+json; okta-version=1.0.0";
+
+/// Polls for an Okta Verify push every second, unless Okta asks for another interval.
+const DEFAULT_POLL_INTERVAL: u64 = 1000;
+
+/// Goes through the remediations of Okta Identity Engine to finally create a session.
+///
+/// The flow starts with the state token of the app's sign-in page, which Okta exchanges for a
+/// session once the user identified themselves and answered the challenges. See
+/// <https://developer.okta.com/docs/guides/oie-intro/main/> for more details.
+pub struct IdxClient {
+    client: ApiClient,
+    enable_desktop_notifications: bool,
+}
+
+impl IdxClient {
+    /// Creates a new [`IdxClient`] object.
+    pub fn new(enable_desktop_notifications: bool) -> Result<IdxClient> {
+        let client = ApiClient::new()?;
+        Ok(IdxClient {
+            client,
+            enable_desktop_notifications,
+        })
+    }
+
+    /// Whether the org of the app runs on Okta Identity Engine.
+    pub async fn is_identity_engine(&self, app_url: &str) -> Result<bool> {
+        let url = api_url(app_url, "/.well-known/okta-organization")?;
+        let response = self.client.get(url, None, None, AcceptType::Json).await?;
+        let organization: Organization = serde_json::from_str(response.text().await?.as_str())?;
+
+        Ok(organization.is_identity_engine())
+    }
+
+    /// Runs the authentication process for an app/username/password.
+    pub async fn run(
+        &self,
+        app_url: String,
+        username: String,
+        password: String,
+        mfa: Option<MfaSelection>,
+        mfa_provider: Option<String>,
+    ) -> Result<OktaSession> {
+        let state_token = self.state_token(app_url.as_str()).await?;
+        let url = api_url(app_url.as_str(), "/idp/idx/introspect")?;
+        let mut response = self
+            .post(
+                url.as_str(),
+                serde_json::json!({ "stateToken": state_token }),
+            )
+            .await?;
+        // the password is only sent once, Okta asking for it again means it was wrong
+        let mut password = Some(password);
+        // the number of a number challenge, once it was shown to the user
+        let mut shown_answer = None;
+
+        // loop over the remediations until Okta redirects to the session or returns an error.
+        loop {
+            let errors = response.errors();
+            if !errors.is_empty() {
+                return Err(idx_error(&errors));
+            }
+            if let Some(success) = response.success() {
+                return self.finish(app_url.as_str(), &success).await;
+            }
+
+            let state_handle = response.state_handle().ok_or_else(|| {
+                Error::UnexpectedResponse(String::from("could not get state handle"))
+            })?;
+
+            response = if let Some(remediation) = response.remediation("identify") {
+                let mut json = serde_json::json!({
+                    "identifier": username,
+                    "stateHandle": state_handle,
+                });
+                if remediation.has_field("credentials") {
+                    let passcode = password.take().ok_or(Error::InvalidCredentials)?;
+                    json["credentials"] = serde_json::json!({ "passcode": passcode });
+                }
+
+                self.answer(&remediation, json).await?
+            } else if let Some(remediation) = response.remediation("challenge-authenticator") {
+                let sends_password = password.is_some() && response.challenges_password();
+                let credentials = self.credentials(&response, app_url.as_str(), &mut password)?;
+                let json = serde_json::json!({
+                    "credentials": credentials,
+                    "stateHandle": state_handle,
+                });
+
+                match sends_password {
+                    true => self.answer(&remediation, json).await?,
+                    false => self.post(remediation.href().as_str(), json).await?,
+                }
+            } else if let Some(remediation) = response.remediation("challenge-poll") {
+                let correct_answer = response
+                    .current_authenticator()
+                    .and_then(|authenticator| authenticator.correct_answer());
+                if let Some(correct_answer) = correct_answer {
+                    if shown_answer != Some(correct_answer) {
+                        show_number_challenge(correct_answer, self.enable_desktop_notifications);
+                        shown_answer = Some(correct_answer);
+                    }
+                }
+
+                let interval = remediation.refresh().unwrap_or(DEFAULT_POLL_INTERVAL);
+                tokio::time::sleep(Duration::from_millis(interval)).await;
+
+                let json = serde_json::json!({ "stateHandle": state_handle });
+                self.post(remediation.href().as_str(), json).await?
+            } else if let Some(remediation) =
+                response.remediation("select-authenticator-authenticate")
+            {
+                let option = self.selected_authenticator(
+                    &response,
+                    &remediation,
+                    password.is_some(),
+                    mfa,
+                    mfa_provider.clone(),
+                )?;
+                let mut authenticator = serde_json::json!({ "id": option.id() });
+                if let Some(method_type) = option.method_type() {
+                    authenticator["methodType"] = Value::String(method_type);
+                }
+                let json = serde_json::json!({
+                    "authenticator": authenticator,
+                    "stateHandle": state_handle,
+                });
+
+                self.post(remediation.href().as_str(), json).await?
+            } else {
+                return Err(Error::UnexpectedResponse(format!(
+                    "unsupported remediation: {}",
+                    response.remediation_names().join(", ")
+                )));
+            };
+        }
+    }
+
+    /// Gets the state token of the sign-in page Okta shows for the app without a session.
+    async fn state_token(&self, app_url: &str) -> Result<String> {
+        let response = self
+            .client
+            .get(app_url.to_string(), None, None, AcceptType::Html)
+            .await?;
+        let body = response.text().await?;
+
+        parse_state_token(body.as_str()).ok_or_else(|| {
+            Error::UnexpectedResponse(String::from(
+                "could not find the state token of the sign-in page",
+            ))
+        })
+    }
+
+    /// Follows the success redirect, which sets the session cookie, and returns the session.
+    async fn finish(&self, app_url: &str, success: &Remediation) -> Result<OktaSession> {
+        self.client
+            .get(success.href(), None, None, AcceptType::Html)
+            .await?;
+
+        let url = api_url(app_url, "/api/v1/sessions/me")?;
+        let response = self.client.get(url, None, None, AcceptType::Json).await?;
+        let body = response.text().await?;
+
+        Ok(serde_json::from_str(body.as_str())?)
+    }
+
+    /// The credentials answering the challenge of the current authenticator.
+    ///
+    /// <https://developer.okta.com/docs/reference/api/authenticators-admin/#authenticator-object>
+    fn credentials(
+        &self,
+        response: &IdxResponse,
+        app_url: &str,
+        password: &mut Option<String>,
+    ) -> Result<Value> {
+        let authenticator = response.current_authenticator().ok_or_else(|| {
+            Error::UnexpectedResponse(String::from("could not get current authenticator"))
+        })?;
+
+        if authenticator.key().as_deref() == Some("yubikey_token") {
+            let passcode = ask_user("Hardware Token Code: ")?;
+
+            return Ok(serde_json::json!({ "passcode": passcode }));
+        }
+
+        match authenticator.authenticator_type().as_deref() {
+            Some("password") => {
+                let passcode = password.take().ok_or(Error::InvalidCredentials)?;
+
+                Ok(serde_json::json!({ "passcode": passcode }))
+            }
+            Some("app") | Some("otp") => {
+                let passcode = ask_user("TOTP Code: ")?;
+
+                Ok(serde_json::json!({ "passcode": passcode }))
+            }
+            Some("phone") => {
+                let passcode = ask_user("Phone Code: ")?;
+
+                Ok(serde_json::json!({ "passcode": passcode }))
+            }
+            Some("email") => {
+                let passcode = ask_user("Email Code: ")?;
+
+                Ok(serde_json::json!({ "passcode": passcode }))
+            }
+            Some("security_key") => {
+                let challenge = authenticator.challenge().ok_or_else(|| {
+                    Error::UnexpectedResponse(String::from("could not get challenge"))
+                })?;
+                let signature = sign_webauthn(
+                    app_url,
+                    challenge,
+                    response.credential_ids(),
+                    self.enable_desktop_notifications,
+                )?;
+
+                Ok(serde_json::json!({
+                    "clientData": signature.client_data,
+                    "authenticatorData": signature.authenticator_data,
+                    "signatureData": signature.signature_data,
+                }))
+            }
+            authenticator_type => Err(Error::UnexpectedResponse(format!(
+                "unsupported authenticator {}",
+                authenticator_type.unwrap_or("without a type")
+            ))),
+        }
+    }
+
+    /// Chooses the password while it wasn't sent, and otherwise the authenticator matching the
+    /// MFA selection, or the one the user picks.
+    fn selected_authenticator(
+        &self,
+        response: &IdxResponse,
+        remediation: &Remediation,
+        with_password: bool,
+        mfa: Option<MfaSelection>,
+        mfa_provider: Option<String>,
+    ) -> Result<AuthenticatorOption> {
+        let keys: HashMap<String, String> = response
+            .authenticators()
+            .into_iter()
+            .filter_map(|authenticator| Some((authenticator.id()?, authenticator.key()?)))
+            .collect();
+        let key = |option: &AuthenticatorOption| keys.get(&option.id()).cloned();
+
+        let (passwords, options): (Vec<AuthenticatorOption>, Vec<AuthenticatorOption>) =
+            remediation
+                .authenticator_options()
+                .into_iter()
+                .partition(|option| key(option).as_deref() == Some("okta_password"));
+        if with_password {
+            if let Some(password) = passwords.into_iter().next() {
+                return Ok(password);
+            }
+        }
+
+        match mfa {
+            Some(mfa) => options
+                .into_iter()
+                .find(|option| {
+                    let key = key(option);
+                    let method_type = option.method_type();
+                    match mfa {
+                        MfaSelection::OktaPush => {
+                            key.as_deref() == Some("okta_verify")
+                                && method_type.as_deref() == Some("push")
+                        }
+                        MfaSelection::Totp => {
+                            let provider = match (key.as_deref(), method_type.as_deref()) {
+                                (Some("okta_verify"), Some("totp")) => "okta",
+                                (Some("google_otp"), _) => "google",
+                                _ => return false,
+                            };
+                            match &mfa_provider {
+                                Some(mfa_provider) => mfa_provider.to_lowercase() == provider,
+                                None => true,
+                            }
+                        }
+                        MfaSelection::WebAuthn => key.as_deref() == Some("webauthn"),
+                        MfaSelection::Sms => {
+                            key.as_deref() == Some("phone_number")
+                                && method_type.as_deref() == Some("sms")
+                        }
+                        MfaSelection::Call => {
+                            key.as_deref() == Some("phone_number")
+                                && method_type.as_deref() == Some("voice")
+                        }
+                        MfaSelection::Email => key.as_deref() == Some("okta_email"),
+                        MfaSelection::HardwareToken => key.as_deref() == Some("yubikey_token"),
+                        MfaSelection::Invalid => false,
+                    }
+                })
+                .ok_or(Error::FactorNotFound),
+            None => {
+                let labels: Vec<String> = options.iter().map(AuthenticatorOption::label).collect();
+                let selection = ask_user_to_select("Please select an authenticator:", &labels)?;
+
+                Ok(options[selection].clone())
+            }
+        }
+    }
+
+    /// Posts the username or the password, a rejection means invalid credentials.
+    async fn answer(&self, remediation: &Remediation, json: Value) -> Result<IdxResponse> {
+        self.post(remediation.href().as_str(), json)
+            .await
+            .map_err(|e| match e {
+                Error::HttpStatus {
+                    code: 400 | 401, ..
+                } => Error::InvalidCredentials,
+                e => e,
+            })
+    }
+
+    async fn post(&self, url: &str, json: Value) -> Result<IdxResponse> {
+        let mut headers = HashMap::new();
+        headers.insert(String::from("accept"), String::from(ION_JSON));
+        headers.insert(String::from("content-type"), String::from(ION_JSON));
+
+        let response = self
+            .client
+            .post_json_with_headers(url, &json, Some(headers))
+            .await?;
+        let body = response.text().await?;
+
+        Ok(serde_json::from_str(body.as_str())?)
+    }
+}
+
+/// Finds the state token in the sign-in page, where it's a JavaScript string with escaped
+/// characters like `\x2D`.
+fn parse_state_token(page: &str) -> Option<String> {
+    let pattern = Regex::new(r#"stateToken\s*[=:]\s*['"]([^'"]+)['"]"#).ok()?;
+    let escaped = pattern.captures(page)?.get(1)?.as_str();
+    let escape = Regex::new(r"\\x([0-9A-Fa-f]{2})").ok()?;
+
+    let token = escape.replace_all(escaped, |captures: &regex::Captures| {
+        u8::from_str_radix(&captures[1], 16)
+            .map(|byte| char::from(byte).to_string())
+            .unwrap_or_default()
+    });
+
+    Some(token.to_string())
+}
+
+/// Turns the error messages of Okta into the matching error.
+fn idx_error(errors: &[Message]) -> Error {
+    let keys: Vec<String> = errors.iter().filter_map(Message::key).collect();
+    let has_key = |part: &str| keys.iter().any(|key| key.contains(part));
+
+    if has_key("rejected") {
+        Error::MfaRejected
+    } else if has_key("timeout") || has_key("expired") {
+        Error::MfaTimeout
+    } else if has_key("E0000004") || has_key("authentication.failed") {
+        Error::InvalidCredentials
+    } else {
+        let messages: Vec<String> = errors.iter().map(Message::message).collect();
+        Error::UnexpectedResponse(messages.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    fn remediation(server: &MockServer, name: &str, path: &str, fields: Value) -> Value {
+        serde_json::json!({
+            "name": name,
+            "href": server.url(path),
+            "method": "POST",
+            "value": fields,
+        })
+    }
+
+    #[test]
+    fn test_parse_state_token() {
+        let page = r#"<script>var stateToken = '02aB\x2DcD\x2D3';var other = 'x';</script>"#;
+
+        assert_eq!(parse_state_token(page), Some(String::from("02aB-cD-3")));
+        assert_eq!(parse_state_token("<html></html>"), None);
+    }
+
+    #[tokio::test]
+    async fn test_run_with_password_and_push() {
+        let server = MockServer::start();
+        let authenticators = serde_json::json!({ "value": [
+            { "id": "autPassword", "key": "okta_password", "type": "password" },
+            { "id": "autVerify", "key": "okta_verify", "type": "app" },
+        ]});
+        let select = remediation(
+            &server,
+            "select-authenticator-authenticate",
+            "/idp/idx/challenge",
+            serde_json::json!([{ "name": "authenticator", "options": [
+                { "label": "Password", "value": { "form": { "value": [
+                    { "name": "id", "value": "autPassword" },
+                    { "name": "methodType", "value": "password" },
+                ]}}},
+                { "label": "Okta Verify", "value": { "form": { "value": [
+                    { "name": "id", "value": "autVerify" },
+                    { "name": "methodType", "options": [
+                        { "label": "Enter a code", "value": "totp" },
+                        { "label": "Get a push notification", "value": "push" },
+                    ]},
+                ]}}},
+            ]}]),
+        );
+
+        server.mock(|when, then| {
+            when.method("GET").path("/home/amazon_aws/0oa/272");
+            then.status(200)
+                .body(r#"<script>var stateToken = 'TheState\x2DToken';</script>"#);
+        });
+        server.mock(|when, then| {
+            when.method("POST")
+                .path("/idp/idx/introspect")
+                .header("accept", ION_JSON)
+                .json_body(serde_json::json!({ "stateToken": "TheState-Token" }));
+            then.status(200).json_body(serde_json::json!({
+                "stateHandle": "TheHandle",
+                "remediation": { "value": [remediation(
+                    &server,
+                    "identify",
+                    "/idp/idx/identify",
+                    serde_json::json!([{ "name": "identifier" }, { "name": "stateHandle" }]),
+                )]},
+            }));
+        });
+        server.mock(|when, then| {
+            when.method("POST")
+                .path("/idp/idx/identify")
+                .json_body(serde_json::json!({
+                    "identifier": "the-user",
+                    "stateHandle": "TheHandle",
+                }));
+            then.status(200).json_body(serde_json::json!({
+                "stateHandle": "TheHandle",
+                "authenticators": authenticators,
+                "remediation": { "value": [select] },
+            }));
+        });
+        let password_mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/idp/idx/challenge")
+                .json_body_partial(r#"{"authenticator":{"id":"autPassword"}}"#);
+            then.status(200).json_body(serde_json::json!({
+                "stateHandle": "TheHandle",
+                "currentAuthenticatorEnrollment": { "value": { "type": "password" } },
+                "remediation": { "value": [remediation(
+                    &server,
+                    "challenge-authenticator",
+                    "/idp/idx/challenge/answer",
+                    serde_json::json!([{ "name": "credentials" }]),
+                )]},
+            }));
+        });
+        server.mock(|when, then| {
+            when.method("POST")
+                .path("/idp/idx/challenge/answer")
+                .json_body(serde_json::json!({
+                    "credentials": { "passcode": "the-password" },
+                    "stateHandle": "TheHandle",
+                }));
+            then.status(200).json_body(serde_json::json!({
+                "stateHandle": "TheHandle",
+                "authenticators": authenticators,
+                "remediation": { "value": [select] },
+            }));
+        });
+        let push_mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/idp/idx/challenge")
+                .json_body(serde_json::json!({
+                    "authenticator": { "id": "autVerify", "methodType": "push" },
+                    "stateHandle": "TheHandle",
+                }));
+            then.status(200).json_body(serde_json::json!({
+                "stateHandle": "TheHandle",
+                "remediation": { "value": [{
+                    "name": "challenge-poll",
+                    "href": server.url("/idp/idx/authenticators/poll"),
+                    "refresh": 1,
+                }]},
+            }));
+        });
+        server.mock(|when, then| {
+            when.method("POST").path("/idp/idx/authenticators/poll");
+            then.status(200).json_body(serde_json::json!({
+                "stateHandle": "TheHandle",
+                "success": {
+                    "name": "success-redirect",
+                    "href": server.url("/login/token/redirect?stateToken=TheState-Token"),
+                },
+            }));
+        });
+        server.mock(|when, then| {
+            when.method("GET").path("/login/token/redirect");
+            then.status(200)
+                .header("set-cookie", "sid=TheSession; Path=/");
+        });
+        let session_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v1/sessions/me")
+                .header("cookie", "sid=TheSession");
+            then.status(200).body(
+                r#"{"id":"TheSession","userId":"User","expiresAt":"2030-01-01T00:00:00.000Z","status":"ACTIVE"}"#,
+            );
+        });
+
+        let client = IdxClient::new(false).unwrap();
+        let session = client
+            .run(
+                server.url("/home/amazon_aws/0oa/272"),
+                String::from("the-user"),
+                String::from("the-password"),
+                Some(MfaSelection::OktaPush),
+                None,
+            )
+            .await
+            .unwrap();
+
+        password_mock.assert();
+        push_mock.assert();
+        session_mock.assert();
+        assert_eq!(session.id(), "TheSession");
+    }
+
+    #[tokio::test]
+    async fn test_run_rejected_push() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method("GET").path("/app");
+            then.status(200).body(r#"stateToken: "TheToken","#);
+        });
+        server.mock(|when, then| {
+            when.method("POST").path("/idp/idx/introspect");
+            then.status(200).json_body(serde_json::json!({
+                "stateHandle": "TheHandle",
+                "messages": { "value": [{
+                    "message": "You have chosen to reject this login.",
+                    "i18n": { "key": "oie.okta_verify.push.rejected" },
+                    "class": "ERROR",
+                }]},
+            }));
+        });
+
+        let client = IdxClient::new(false).unwrap();
+        let result = client
+            .run(
+                server.url("/app"),
+                String::from("the-user"),
+                String::from("the-password"),
+                None,
+                None,
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::MfaRejected)));
+    }
+}
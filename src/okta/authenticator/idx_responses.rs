@@ -0,0 +1,338 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+/// A response of the Identity Engine, listing the remediations which move the authentication
+/// forward.
+///
+/// <https://developer.okta.com/docs/guides/oie-intro/main/#remediation>
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IdxResponse {
+    state_handle: Option<String>,
+    remediation: Option<Collection<Remediation>>,
+    current_authenticator: Option<Wrapped<Authenticator>>,
+    current_authenticator_enrollment: Option<Wrapped<Authenticator>>,
+    authenticators: Option<Collection<Authenticator>>,
+    authenticator_enrollments: Option<Collection<Authenticator>>,
+    messages: Option<Collection<Message>>,
+    success: Option<Remediation>,
+}
+
+impl IdxResponse {
+    /// Tries to return the state handle to keep track of the transaction.
+    pub fn state_handle(&self) -> Option<String> {
+        self.state_handle.clone()
+    }
+
+    /// Tries to return the remediation of the given name.
+    pub fn remediation(&self, name: &str) -> Option<Remediation> {
+        self.remediation
+            .as_ref()?
+            .value
+            .iter()
+            .find(|remediation| remediation.name == name)
+            .cloned()
+    }
+
+    /// The names of all remediations, to tell the user about the ones c9s doesn't support.
+    pub fn remediation_names(&self) -> Vec<String> {
+        self.remediation
+            .as_ref()
+            .map(|remediation| {
+                remediation
+                    .value
+                    .iter()
+                    .map(|remediation| remediation.name.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The authenticator which is challenged, or else the enrollment of it.
+    pub fn current_authenticator(&self) -> Option<Authenticator> {
+        self.current_authenticator
+            .as_ref()
+            .or(self.current_authenticator_enrollment.as_ref())
+            .map(|current| current.value.clone())
+    }
+
+    /// Whether the current challenge asks for the password.
+    pub fn challenges_password(&self) -> bool {
+        self.current_authenticator()
+            .and_then(|authenticator| authenticator.authenticator_type())
+            .as_deref()
+            == Some("password")
+    }
+
+    /// The authenticators the user could select.
+    pub fn authenticators(&self) -> Vec<Authenticator> {
+        self.authenticators
+            .as_ref()
+            .map(|authenticators| authenticators.value.clone())
+            .unwrap_or_default()
+    }
+
+    /// The credential IDs of the user's security keys.
+    pub fn credential_ids(&self) -> Vec<String> {
+        self.authenticator_enrollments
+            .as_ref()
+            .map(|enrollments| {
+                enrollments
+                    .value
+                    .iter()
+                    .filter_map(|enrollment| enrollment.credential_id.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The error messages Okta returned for the last request.
+    pub fn errors(&self) -> Vec<Message> {
+        self.messages
+            .as_ref()
+            .map(|messages| {
+                messages
+                    .value
+                    .iter()
+                    .filter(|message| message.class.as_deref() == Some("ERROR"))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Tries to return the redirect which finishes a successful authentication.
+    pub fn success(&self) -> Option<Remediation> {
+        self.success.clone()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Collection<T> {
+    value: Vec<T>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Wrapped<T> {
+    value: T,
+}
+
+/// A step of the authentication, posted as a form to `href`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Remediation {
+    name: String,
+    href: String,
+    #[serde(default)]
+    value: Vec<FormField>,
+    /// Milliseconds to wait before polling again.
+    refresh: Option<u64>,
+}
+
+impl Remediation {
+    pub fn href(&self) -> String {
+        self.href.clone()
+    }
+
+    pub fn refresh(&self) -> Option<u64> {
+        self.refresh
+    }
+
+    /// Whether the form of the remediation has the field.
+    pub fn has_field(&self, name: &str) -> bool {
+        self.value.iter().any(|field| field.name == name)
+    }
+
+    /// The authenticators, and their methods, offered by a `select-authenticator-authenticate`
+    /// remediation.
+    pub fn authenticator_options(&self) -> Vec<AuthenticatorOption> {
+        self.value
+            .iter()
+            .find(|field| field.name == "authenticator")
+            .and_then(|field| field.options.as_ref())
+            .map(|options| {
+                options
+                    .iter()
+                    .flat_map(FormOption::authenticator_options)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct FormField {
+    name: String,
+    value: Option<Value>,
+    options: Option<Vec<FormOption>>,
+}
+
+impl FormField {
+    fn string_value(&self) -> Option<String> {
+        self.value.as_ref()?.as_str().map(String::from)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct FormOption {
+    label: String,
+    value: Value,
+}
+
+impl FormOption {
+    /// An option of the authenticator field is a form with the authenticator's `id` and its
+    /// `methodType`, which has options itself for authenticators like Okta Verify.
+    fn authenticator_options(&self) -> Vec<AuthenticatorOption> {
+        let fields: Vec<FormField> = self
+            .value
+            .pointer("/form/value")
+            .and_then(|fields| serde_json::from_value(fields.clone()).ok())
+            .unwrap_or_default();
+        let field = |name: &str| fields.iter().find(|field| field.name == name);
+
+        let id = match field("id").and_then(FormField::string_value) {
+            Some(id) => id,
+            None => return vec![],
+        };
+        let option = |method_type: Option<String>, label: String| AuthenticatorOption {
+            id: id.clone(),
+            method_type,
+            label,
+        };
+
+        match field("methodType") {
+            Some(FormField {
+                options: Some(methods),
+                ..
+            }) => methods
+                .iter()
+                .map(|method| {
+                    option(
+                        method.value.as_str().map(String::from),
+                        format!("{} ({})", self.label, method.label),
+                    )
+                })
+                .collect(),
+            Some(method) => vec![option(method.string_value(), self.label.clone())],
+            None => vec![option(None, self.label.clone())],
+        }
+    }
+}
+
+/// An authenticator, and one of its methods, the user can select.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatorOption {
+    id: String,
+    method_type: Option<String>,
+    label: String,
+}
+
+impl AuthenticatorOption {
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    pub fn method_type(&self) -> Option<String> {
+        self.method_type.clone()
+    }
+
+    pub fn label(&self) -> String {
+        self.label.clone()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Authenticator {
+    id: Option<String>,
+    key: Option<String>,
+    #[serde(rename = "type")]
+    authenticator_type: Option<String>,
+    credential_id: Option<String>,
+    contextual_data: Option<ContextualData>,
+}
+
+impl Authenticator {
+    pub fn id(&self) -> Option<String> {
+        self.id.clone()
+    }
+
+    /// The kind of authenticator, e.g. `okta_password`, `okta_verify`, `google_otp` or
+    /// `webauthn`.
+    pub fn key(&self) -> Option<String> {
+        self.key.clone()
+    }
+
+    /// The type of authenticator, e.g. `password`, `app`, `email` or `security_key`.
+    pub fn authenticator_type(&self) -> Option<String> {
+        self.authenticator_type.clone()
+    }
+
+    /// Tries to return the number the user has to pick in Okta Verify.
+    pub fn correct_answer(&self) -> Option<u32> {
+        self.contextual_data.as_ref()?.correct_answer
+    }
+
+    /// Tries to return the WebAuthn challenge.
+    pub fn challenge(&self) -> Option<String> {
+        Some(
+            self.contextual_data
+                .as_ref()?
+                .challenge_data
+                .as_ref()?
+                .challenge
+                .clone(),
+        )
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ContextualData {
+    challenge_data: Option<ChallengeData>,
+    /// The number to pick in Okta Verify for a push with number matching.
+    correct_answer: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ChallengeData {
+    challenge: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Message {
+    message: String,
+    class: Option<String>,
+    i18n: Option<I18n>,
+}
+
+impl Message {
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    /// The key identifying the message, e.g. `oie.okta_verify.push.rejected`.
+    pub fn key(&self) -> Option<String> {
+        Some(self.i18n.as_ref()?.key.clone())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct I18n {
+    key: String,
+}
+
+/// Okta's description of an org, which tells whether it runs on Identity Engine.
+///
+/// <https://developer.okta.com/docs/guides/oie-upgrade-overview/main/#determine-your-okta-org-version>
+#[derive(Deserialize, Debug)]
+pub struct Organization {
+    pipeline: String,
+}
+
+impl Organization {
+    pub fn is_identity_engine(&self) -> bool {
+        self.pipeline == "idx"
+    }
+}
@@ -1,2 +1,90 @@
 mod api_responses;
 pub mod authenticator_client;
+mod idx_client;
+mod idx_responses;
+
+pub use idx_client::IdxClient;
+
+use crate::error::{Error, Result};
+use notify_rust::Notification;
+use std::io::{self, BufRead, Write};
+use tmuntaner_webauthn::{SignatureResponse, WebauthnClient};
+use url::Url;
+
+/// Asks the user to choose one of the options, returning its index.
+fn ask_user_to_select(title: &str, options: &[String]) -> Result<usize> {
+    let min: usize = 0;
+    let max: usize = options.len();
+
+    eprintln!("{title}");
+    for (i, option) in options.iter().enumerate() {
+        eprintln!("( {i} ) {option}");
+    }
+
+    let buffer = ask_user(format!("Selection? ({min} - {max}) ").as_str())?;
+    let selection: usize = buffer
+        .parse()
+        .map_err(|_| Error::UserInput(String::from("failed to parse your selection")))?;
+    if selection >= max {
+        return Err(Error::UserInput(String::from(
+            "you've selected an invalid option",
+        )));
+    }
+
+    Ok(selection)
+}
+
+/// Reads a line from stdin after printing the prompt.
+fn ask_user(prompt: &str) -> Result<String> {
+    eprint!("{prompt}");
+    let _ = io::stdout().flush();
+    let mut buffer = String::new();
+    io::stdin().lock().read_line(&mut buffer)?;
+    // remove \n on unix or \r\n on windows
+    let len = buffer.trim_end_matches(&['\r', '\n'][..]).len();
+    buffer.truncate(len);
+
+    Ok(buffer)
+}
+
+/// Tells the user which number to pick in Okta Verify.
+fn show_number_challenge(correct_answer: u32, enable_desktop_notifications: bool) {
+    let message = format!("Select {correct_answer} in Okta Verify to confirm the login.");
+    eprintln!("{message}");
+
+    if enable_desktop_notifications {
+        // the number is on stderr already, a missing notification daemon isn't an error
+        let _ = Notification::new()
+            .summary("Okta Verify")
+            .body(message.as_str())
+            .show();
+    }
+}
+
+/// Signs a WebAuthn challenge of the Okta host with one of the security keys.
+fn sign_webauthn(
+    app_url: &str,
+    challenge: String,
+    credential_ids: Vec<String>,
+    enable_desktop_notifications: bool,
+) -> Result<SignatureResponse> {
+    let origin = Url::parse(app_url)?;
+    if origin.scheme() != "https" {
+        return Err(Error::Config(String::from("U2F request should be https")));
+    }
+
+    let host = origin
+        .host()
+        .ok_or_else(|| Error::Config(String::from("couldn't get host from url")))?
+        .to_string();
+
+    let mut webauthn_client = WebauthnClient::new();
+    if enable_desktop_notifications {
+        webauthn_client.add_desktop_notification_notifier();
+    }
+    webauthn_client.add_progress_bar_notifier();
+
+    webauthn_client
+        .sign(challenge, host, credential_ids)
+        .map_err(|e| Error::WebAuthn(e.to_string()))
+}
@@ -1,51 +1,73 @@
-use crate::aws::sts::StsClient;
-use crate::aws::Credential;
+use crate::aws::role_filter::RoleFilter;
+use crate::aws::sts::{StsClient, StsOptions};
+use crate::aws::{Credential, Role};
+use crate::error::{Error, Result};
 use crate::http::api_client::{AcceptType, ApiClient};
 use crate::okta::saml_parsers::OktaAwsSamlParser;
-use anyhow::{anyhow, Result};
-use std::collections::HashMap;
+use crate::okta::session::OktaSession;
 
 /// This struct contacts the AWS application in Okta, goes through its SAML response, and then
 /// uses the result to generate credentials with STS.
 pub struct AwsCredentials {
     client: ApiClient,
-    sts_client: StsClient,
 }
 
 impl AwsCredentials {
     /// Generates a new [`AwsCredentials`] object.
     pub fn new() -> Result<AwsCredentials> {
         let client = ApiClient::new()?;
-        let sts_client = StsClient::new()?;
-        Ok(AwsCredentials { client, sts_client })
+        Ok(AwsCredentials { client })
     }
 
     /// Call this function to get credentials from the AWS.
+    ///
+    /// Without a role ARN, all roles matching the filter are assumed.
     pub async fn run(
         &self,
         app_url: String,
-        session_token: String,
+        session: &OktaSession,
         role_arn: Option<String>,
+        filter: &RoleFilter,
+        sts_options: StsOptions,
     ) -> Result<Vec<Credential>> {
-        let mut params = HashMap::new();
-        params.insert(String::from("sessionToken"), session_token);
+        let body = self.saml_body(app_url, session).await?;
 
-        let response = self
-            .client
-            .get(app_url, Some(params), None, AcceptType::Json)
+        let aws_credentials = self
+            .get_saml_response(body, role_arn, filter, sts_options)
             .await?;
 
-        let body = response.text().await?;
+        Ok(aws_credentials)
+    }
 
-        let aws_credentials = self.get_saml_response(body.clone(), role_arn).await?;
+    /// Call this function to list the roles in the SAML response, without assuming them.
+    pub async fn roles(&self, app_url: String, session: &OktaSession) -> Result<Vec<Role>> {
+        let body = self.saml_body(app_url, session).await?;
+        let saml_parser = OktaAwsSamlParser::new(body)?;
 
-        Ok(aws_credentials)
+        saml_parser
+            .credentials()?
+            .iter()
+            .map(|role| {
+                Ok(Role::from_arn(&role.role_arn)?.with_principal_arn(role.principal_arn.clone()))
+            })
+            .collect()
+    }
+
+    async fn saml_body(&self, app_url: String, session: &OktaSession) -> Result<String> {
+        let response = self
+            .client
+            .get(app_url, None, Some(session.headers()), AcceptType::Json)
+            .await?;
+
+        Ok(response.text().await?)
     }
 
     async fn get_saml_response(
         &self,
         body: String,
         role_arn: Option<String>,
+        filter: &RoleFilter,
+        sts_options: StsOptions,
     ) -> Result<Vec<Credential>> {
         let saml_parser = OktaAwsSamlParser::new(body)?;
         let saml_aws_credentials = match role_arn {
@@ -54,14 +76,30 @@ impl AwsCredentials {
                 let role = credentials
                     .iter()
                     .find(|cred| cred.role_arn == role_arn)
-                    .ok_or_else(|| anyhow!("could not find role_arn {}", role_arn))?;
+                    .ok_or_else(|| Error::RoleNotFound(role_arn.clone()))?;
                 vec![role.clone()]
             }
-            None => saml_parser.credentials()?,
+            None if filter.is_empty() => saml_parser.credentials()?,
+            None => {
+                let mut credentials = vec![];
+                for credential in saml_parser.credentials()? {
+                    if filter.matches(&Role::from_arn(&credential.role_arn)?) {
+                        credentials.push(credential);
+                    }
+                }
+                if credentials.is_empty() {
+                    return Err(Error::RoleNotFound(String::from("matching the filters")));
+                }
+                credentials
+            }
         };
-        let aws_credentials = self
-            .sts_client
-            .generate_sts_credentials(saml_parser.raw_saml_response(), saml_aws_credentials)
+        let sts_client = StsClient::new(sts_options)?;
+        let aws_credentials = sts_client
+            .generate_sts_credentials(
+                saml_parser.raw_saml_response(),
+                saml_aws_credentials,
+                saml_parser.session_duration()?,
+            )
             .await?;
 
         Ok(aws_credentials)
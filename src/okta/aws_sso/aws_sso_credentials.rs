@@ -1,7 +1,9 @@
+use crate::aws::role_filter::RoleFilter;
 use crate::aws::sso_portal_client::SsoPortalClient;
-use crate::aws::{Credential, Role};
+use crate::aws::{Credential, Role, SsoPortalEndpoint, SsoPortalToken};
+use crate::error::Result;
 use crate::okta::aws_sso::sso_portal_login::SsoPortalLogin;
-use anyhow::Result;
+use crate::okta::session::OktaSession;
 
 pub struct AwsSSOCredentials {
     sso_portal_login: SsoPortalLogin,
@@ -14,30 +16,51 @@ impl AwsSSOCredentials {
         Ok(AwsSSOCredentials { sso_portal_login })
     }
 
+    /// Call this function to log into the AWS SSO portal with the Okta session.
+    ///
+    /// The token can be reused for all following portal requests until it expires.
+    pub async fn token(
+        &self,
+        app_url: String,
+        session: &OktaSession,
+        endpoint: &SsoPortalEndpoint,
+    ) -> Result<SsoPortalToken> {
+        self.sso_portal_login
+            .run(app_url, session, endpoint.url())
+            .await
+    }
+
     /// Call this function to get credentials from AWS SSO.
     pub async fn run(
         &self,
-        app_url: String,
-        session_token: String,
-        region: String,
+        endpoint: &SsoPortalEndpoint,
+        token: &SsoPortalToken,
         role_arn: Option<String>,
+        filter: &RoleFilter,
     ) -> Result<Vec<Credential>> {
-        let portal_url = format!("https://portal.sso.{region}.amazonaws.com");
-        let token = self
-            .sso_portal_login
-            .run(app_url, session_token, portal_url.clone())
-            .await?;
-        let sso_client = SsoPortalClient::new(portal_url)?;
+        let sso_client = SsoPortalClient::new(endpoint.url(), endpoint.partition())?;
 
         let roles = match role_arn {
             Some(arn) => {
                 let role_arn = Role::from_arn(&arn)?;
                 vec![role_arn]
             }
-            None => sso_client.list_role_arns(token.clone()).await?,
+            None if filter.is_empty() => sso_client.list_role_arns(token.token()).await?,
+            None => filter.apply(sso_client.list_role_arns(token.token()).await?)?,
         };
-        let credentials = sso_client.list_credentials(token, roles).await?;
+        let credentials = sso_client.list_credentials(token.token(), roles).await?;
 
         Ok(credentials)
     }
+
+    /// Call this function to list the roles available in AWS SSO, without generating credentials.
+    pub async fn roles(
+        &self,
+        endpoint: &SsoPortalEndpoint,
+        token: &SsoPortalToken,
+    ) -> Result<Vec<Role>> {
+        let sso_client = SsoPortalClient::new(endpoint.url(), endpoint.partition())?;
+
+        sso_client.list_role_arns(token.token()).await
+    }
 }
@@ -1,10 +1,17 @@
+use crate::aws::SsoPortalToken;
+use crate::error::{Error, Result};
 use crate::http::api_client::{AcceptType, ApiClient};
 use crate::okta::saml_parsers::OktaAwsSsoSamlParser;
-use anyhow::{anyhow, Result};
+use crate::okta::session::OktaSession;
 use serde::Deserialize;
 use std::collections::HashMap;
+use time::{Duration, OffsetDateTime};
 use url::Url;
 
+/// How long c9s uses a portal token. The portal doesn't tell when its tokens expire, AWS SSO
+/// sessions last at least an hour by default, and tokens rejected earlier are discarded.
+const PORTAL_TOKEN_LIFETIME: Duration = Duration::hours(1);
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct TokenResponse {
@@ -35,10 +42,10 @@ impl SsoPortalLogin {
     pub async fn run(
         &self,
         app_url: String,
-        session_token: String,
+        session: &OktaSession,
         portal_url: String,
-    ) -> Result<String> {
-        let try_saml_response = self.try_saml(app_url, session_token).await?;
+    ) -> Result<SsoPortalToken> {
+        let try_saml_response = self.try_saml(app_url, session).await?;
         let workflow_start = self
             .workflow_start(try_saml_response.saml, try_saml_response.destination)
             .await?;
@@ -46,7 +53,10 @@ impl SsoPortalLogin {
             .token_response(portal_url, workflow_start.auth_code, workflow_start.org_id)
             .await?;
 
-        Ok(token_response.token)
+        SsoPortalToken::new(
+            token_response.token,
+            OffsetDateTime::now_utc() + PORTAL_TOKEN_LIFETIME,
+        )
     }
 
     async fn workflow_start(
@@ -69,27 +79,23 @@ impl SsoPortalLogin {
         let org_id = response
             .url()
             .host_str()
-            .ok_or_else(|| anyhow!("could not get host"))?
-            .to_string()
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get host")))?
             .split('.')
             .next()
-            .ok_or_else(|| anyhow!("could not get org-id"))?
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get org-id")))?
             .to_string();
         let auth_code = hash_query
             .get("workflowResultHandle")
-            .ok_or_else(|| anyhow!("could not get auth code"))?
+            .ok_or_else(|| Error::UnexpectedResponse(String::from("could not get auth code")))?
             .to_owned();
 
         Ok(WorkflowStartResponse { org_id, auth_code })
     }
 
-    async fn try_saml(&self, app_url: String, session_token: String) -> Result<TrySamlResponse> {
-        let mut params = HashMap::new();
-        params.insert(String::from("sessionToken"), session_token);
-
+    async fn try_saml(&self, app_url: String, session: &OktaSession) -> Result<TrySamlResponse> {
         let response = self
             .client
-            .get(app_url, Some(params), None, AcceptType::Html)
+            .get(app_url, None, Some(session.headers()), AcceptType::Html)
             .await?;
         let body = response.text().await?;
 
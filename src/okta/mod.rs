@@ -3,3 +3,4 @@ mod aws;
 mod aws_sso;
 pub mod okta_client;
 mod saml_parsers;
+pub mod session;
@@ -1,14 +1,21 @@
-use crate::aws::Credential;
+use crate::aws::role_filter::RoleFilter;
+use crate::aws::sts::StsOptions;
+use crate::aws::{Credential, Role, SsoPortalEndpoint, SsoPortalToken};
+use crate::error::{Error, Result};
 use crate::okta::authenticator::authenticator_client::AuthenticatorClient;
+use crate::okta::authenticator::IdxClient;
 use crate::okta::aws::aws_credentials::AwsCredentials;
 use crate::okta::aws_sso::aws_sso_credentials::AwsSSOCredentials;
-use anyhow::{anyhow, Result};
+use crate::okta::session::{OktaSession, SessionClient};
+use crate::settings::OktaAuthenticator;
 
 /// This is the entrypoint to communicate with Okta to generate temporary credentials.
 pub struct OktaClient {
     authorizer: AuthenticatorClient,
+    idx: IdxClient,
     aws_credentials: AwsCredentials,
     aws_sso_credentials: AwsSSOCredentials,
+    sessions: SessionClient,
 }
 
 #[derive(Copy, Clone)]
@@ -16,6 +23,10 @@ pub enum MfaSelection {
     WebAuthn,
     Totp,
     OktaPush,
+    Sms,
+    Call,
+    Email,
+    HardwareToken,
     Invalid,
 }
 
@@ -26,6 +37,12 @@ impl MfaSelection {
             "totp" => MfaSelection::Totp,
             "push" => MfaSelection::OktaPush,
             "oktapush" => MfaSelection::OktaPush,
+            "sms" => MfaSelection::Sms,
+            "call" => MfaSelection::Call,
+            "voice" => MfaSelection::Call,
+            "email" => MfaSelection::Email,
+            "hardware" => MfaSelection::HardwareToken,
+            "yubikey" => MfaSelection::HardwareToken,
             _ => MfaSelection::Invalid,
         }
     }
@@ -34,7 +51,7 @@ impl MfaSelection {
         if let Some(mfa) = mfa_option {
             let selection = MfaSelection::from_string(mfa);
             match selection {
-                MfaSelection::Invalid => Err(anyhow!("invalid MFA selection")),
+                MfaSelection::Invalid => Err(Error::Config(String::from("invalid MFA selection"))),
                 _ => Ok(()),
             }
         } else {
@@ -48,54 +65,180 @@ impl OktaClient {
     pub fn new(enable_desktop_notifications: bool) -> Result<OktaClient> {
         Ok(OktaClient {
             authorizer: AuthenticatorClient::new(enable_desktop_notifications)?,
+            idx: IdxClient::new(enable_desktop_notifications)?,
             aws_credentials: AwsCredentials::new()?,
             aws_sso_credentials: AwsSSOCredentials::new()?,
+            sessions: SessionClient::new()?,
         })
     }
 
-    pub async fn aws_credentials(
+    /// Authenticates against Okta and creates a session for the following requests.
+    ///
+    /// With [`OktaAuthenticator::Auto`], the Classic authentication API is tried first and
+    /// Identity Engine is used when the org disabled it.
+    pub async fn login(
         &self,
+        app_url: String,
         username: String,
         password: String,
-        app_url: String,
-        role_arn: Option<String>,
         mfa: Option<MfaSelection>,
         mfa_provider: Option<String>,
-    ) -> Result<Vec<Credential>> {
-        let session_token = self
+        authenticator: OktaAuthenticator,
+    ) -> Result<OktaSession> {
+        if authenticator == OktaAuthenticator::Idx {
+            return self
+                .idx
+                .run(app_url, username, password, mfa, mfa_provider)
+                .await;
+        }
+
+        let session_token = match self
             .authorizer
-            .run(app_url.clone(), username, password, mfa, mfa_provider)
-            .await?;
+            .run(
+                app_url.clone(),
+                username.clone(),
+                password.clone(),
+                mfa,
+                mfa_provider.clone(),
+            )
+            .await
+        {
+            Err(e) if authenticator == OktaAuthenticator::Auto && is_authn_unavailable(&e) => {
+                let identity_engine = self
+                    .idx
+                    .is_identity_engine(app_url.as_str())
+                    .await
+                    .unwrap_or(false);
+                if !identity_engine {
+                    return Err(e);
+                }
 
-        let credentials = self
-            .aws_credentials
-            .run(app_url, session_token, role_arn)
-            .await?;
+                return self
+                    .idx
+                    .run(app_url, username, password, mfa, mfa_provider)
+                    .await;
+            }
+            session_token => session_token?,
+        };
 
-        Ok(credentials)
+        self.sessions.create(app_url.as_str(), session_token).await
     }
 
-    #[allow(clippy::too_many_arguments)]
-    pub async fn aws_sso_credentials(
+    /// Continues a session from an earlier login, see [`SessionClient::resume`].
+    pub async fn resume_session(
         &self,
-        username: String,
-        password: String,
         app_url: String,
-        region: String,
+        session: OktaSession,
+    ) -> Result<Option<OktaSession>> {
+        self.sessions.resume(app_url.as_str(), session).await
+    }
+
+    pub async fn aws_credentials(
+        &self,
+        app_url: String,
+        session: &OktaSession,
         role_arn: Option<String>,
-        mfa: Option<MfaSelection>,
-        mfa_provider: Option<String>,
+        filter: &RoleFilter,
+        sts_options: StsOptions,
     ) -> Result<Vec<Credential>> {
-        let session_token = self
-            .authorizer
-            .run(app_url.clone(), username, password, mfa, mfa_provider)
-            .await?;
+        self.aws_credentials
+            .run(app_url, session, role_arn, filter, sts_options)
+            .await
+    }
+
+    /// Logs into the AWS SSO portal, see [`AwsSSOCredentials::token`].
+    pub async fn sso_portal_token(
+        &self,
+        app_url: String,
+        session: &OktaSession,
+        endpoint: &SsoPortalEndpoint,
+    ) -> Result<SsoPortalToken> {
+        self.aws_sso_credentials
+            .token(app_url, session, endpoint)
+            .await
+    }
+
+    pub async fn aws_sso_credentials(
+        &self,
+        endpoint: &SsoPortalEndpoint,
+        token: &SsoPortalToken,
+        role_arn: Option<String>,
+        filter: &RoleFilter,
+    ) -> Result<Vec<Credential>> {
+        self.aws_sso_credentials
+            .run(endpoint, token, role_arn, filter)
+            .await
+    }
+
+    /// Lists the roles of Okta's AWS application, without assuming them.
+    pub async fn aws_roles(&self, app_url: String, session: &OktaSession) -> Result<Vec<Role>> {
+        self.aws_credentials.roles(app_url, session).await
+    }
+
+    /// Lists the roles of Okta's AWS SSO application, without generating credentials.
+    pub async fn aws_sso_roles(
+        &self,
+        endpoint: &SsoPortalEndpoint,
+        token: &SsoPortalToken,
+    ) -> Result<Vec<Role>> {
+        self.aws_sso_credentials.roles(endpoint, token).await
+    }
+}
+
+/// Whether Okta refused the Classic authentication API itself, rather than the credentials.
+fn is_authn_unavailable(error: &Error) -> bool {
+    match error {
+        Error::HttpStatus { code, url } => {
+            matches!(code, 400 | 403 | 404) && url.ends_with("/api/v1/authn")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    #[tokio::test]
+    async fn test_login_falls_back_to_identity_engine() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method("POST").path("/api/v1/authn");
+            then.status(403);
+        });
+        server.mock(|when, then| {
+            when.method("GET").path("/.well-known/okta-organization");
+            then.status(200).body(r#"{"id":"00o","pipeline":"idx"}"#);
+        });
+        server.mock(|when, then| {
+            when.method("GET").path("/app");
+            then.status(200).body("var stateToken = 'TheToken';");
+        });
+        let introspect_mock = server.mock(|when, then| {
+            when.method("POST").path("/idp/idx/introspect");
+            then.status(200).body(
+                r#"{"messages":{"value":[{"message":"rejected","class":"ERROR","i18n":{"key":"oie.okta_verify.push.rejected"}}]}}"#,
+            );
+        });
+
+        let client = OktaClient::new(false).unwrap();
+        let login = |authenticator| {
+            client.login(
+                server.url("/app"),
+                String::from("the-user"),
+                String::from("the-password"),
+                None,
+                None,
+                authenticator,
+            )
+        };
 
-        let credentials = self
-            .aws_sso_credentials
-            .run(app_url, session_token, region, role_arn)
-            .await?;
+        let classic = login(OktaAuthenticator::Classic).await;
+        let auto = login(OktaAuthenticator::Auto).await;
 
-        Ok(credentials)
+        assert!(matches!(classic, Err(Error::HttpStatus { code: 403, .. })));
+        assert!(matches!(auto, Err(Error::MfaRejected)));
+        introspect_mock.assert();
     }
 }
@@ -1,5 +1,5 @@
 use crate::aws::sts::SamlAWSRole;
-use anyhow::{anyhow, Result};
+use crate::error::{Error, Result};
 use base64::{alphabet, engine, Engine};
 use quick_xml::events::Event;
 use quick_xml::NsReader;
@@ -38,9 +38,10 @@ impl OktaAwsSsoSamlParser {
                     let (_, local) = reader.resolve_element(e.name());
                     if let b"Response" = local.as_ref() {
                         for el in e.attributes() {
-                            let e = el?;
-                            let key = std::str::from_utf8(e.key.as_ref())?;
-                            let value = std::str::from_utf8(e.value.as_ref())?;
+                            let e = el.map_err(saml_error)?;
+                            let key = std::str::from_utf8(e.key.as_ref()).map_err(saml_error)?;
+                            let value =
+                                std::str::from_utf8(e.value.as_ref()).map_err(saml_error)?;
                             if key == "Destination" {
                                 return Ok(value.to_string());
                             }
@@ -53,7 +54,7 @@ impl OktaAwsSsoSamlParser {
             }
         }
 
-        Err(anyhow!("destination not found"))
+        Err(Error::SamlParse(String::from("destination not found")))
     }
 }
 
@@ -77,6 +78,37 @@ impl OktaAwsSamlParser {
     }
 
     pub fn credentials(&self) -> Result<Vec<SamlAWSRole>> {
+        self.attribute_values("https://aws.amazon.com/SAML/Attributes/Role")?
+            .iter()
+            .map(|value| {
+                let split: Vec<&str> = value.split(',').collect();
+                match split.as_slice() {
+                    [principal_arn, role_arn, ..] => Ok(SamlAWSRole {
+                        role_arn: String::from(*role_arn),
+                        principal_arn: String::from(*principal_arn),
+                    }),
+                    _ => Err(Error::SamlParse(format!("invalid role attribute {value}"))),
+                }
+            })
+            .collect()
+    }
+
+    /// The maximum session duration in seconds Okta allows for the roles, if it is configured.
+    pub fn session_duration(&self) -> Result<Option<i32>> {
+        let values =
+            self.attribute_values("https://aws.amazon.com/SAML/Attributes/SessionDuration")?;
+
+        match values.first() {
+            Some(value) => {
+                let duration = value.trim().parse::<i32>().map_err(saml_error)?;
+                Ok(Some(duration))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The values of the attribute with the given name.
+    fn attribute_values(&self, attribute: &str) -> Result<Vec<String>> {
         let body = self.saml_body.clone();
 
         let mut reader = NsReader::from_str(body.as_str());
@@ -85,7 +117,7 @@ impl OktaAwsSamlParser {
 
         #[derive(Clone, Copy)]
         enum State {
-            RoleAttributes,
+            Attribute,
             Other,
         }
 
@@ -97,7 +129,7 @@ impl OktaAwsSamlParser {
 
         let mut name = Name::Other;
         let mut state = State::Other;
-        let mut credentials: Vec<SamlAWSRole> = vec![];
+        let mut values: Vec<String> = vec![];
 
         loop {
             match reader.read_resolved_event_into(&mut buf) {
@@ -106,13 +138,13 @@ impl OktaAwsSamlParser {
                     match local.as_ref() {
                         b"Attribute" => {
                             for el in e.attributes() {
-                                let e = el?;
-                                let key = std::str::from_utf8(e.key.as_ref())?;
-                                let value = std::str::from_utf8(e.value.as_ref())?;
-                                if let ("Name", "https://aws.amazon.com/SAML/Attributes/Role") =
-                                    (key, value)
-                                {
-                                    state = State::RoleAttributes;
+                                let e = el.map_err(saml_error)?;
+                                let key =
+                                    std::str::from_utf8(e.key.as_ref()).map_err(saml_error)?;
+                                let value =
+                                    std::str::from_utf8(e.value.as_ref()).map_err(saml_error)?;
+                                if key == "Name" && value == attribute {
+                                    state = State::Attribute;
                                     break;
                                 }
                             }
@@ -124,14 +156,9 @@ impl OktaAwsSamlParser {
                     }
                 }
                 Ok((_, Event::Text(e))) => {
-                    if let (State::RoleAttributes, Name::AttributeValue) = (state, name) {
-                        let value = std::str::from_utf8(e.as_ref())?;
-                        let split: Vec<&str> = value.split(',').collect();
-
-                        credentials.push(SamlAWSRole {
-                            role_arn: String::from(split[1]),
-                            principal_arn: String::from(split[0]),
-                        })
+                    if let (State::Attribute, Name::AttributeValue) = (state, name) {
+                        let value = std::str::from_utf8(e.as_ref()).map_err(saml_error)?;
+                        values.push(value.to_string());
                     }
                 }
                 Ok((_, Event::End(e))) => {
@@ -146,7 +173,7 @@ impl OktaAwsSamlParser {
             }
         }
 
-        Ok(credentials)
+        Ok(values)
     }
 }
 
@@ -165,16 +192,17 @@ impl BaseSamlParser {
         if let Some(element) = node {
             let response: String = element
                 .attr("value")
-                .map(|value| value.parse())
-                .ok_or_else(|| anyhow!("could not get SAMLResponse"))??;
-            let decoded = String::from_utf8(base64urlsafe.decode(response.clone())?)?;
+                .map(String::from)
+                .ok_or_else(|| Error::SamlParse(String::from("could not get SAMLResponse")))?;
+            let decoded = base64urlsafe.decode(response.clone()).map_err(saml_error)?;
+            let decoded = String::from_utf8(decoded).map_err(saml_error)?;
 
             Ok(BaseSamlParser {
                 raw: response,
                 parsed: decoded,
             })
         } else {
-            Err(anyhow!("could not get SAMLResponse"))
+            Err(Error::SamlParse(String::from("could not get SAMLResponse")))
         }
     }
 
@@ -186,3 +214,63 @@ impl BaseSamlParser {
         self.raw.clone()
     }
 }
+
+fn saml_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::SamlParse(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saml_page(assertion: &str) -> String {
+        let encoded = engine::general_purpose::STANDARD.encode(assertion);
+        format!(
+            r#"<html><body><form><input name="SAMLResponse" type="hidden" value="{encoded}"/></form></body></html>"#
+        )
+    }
+
+    #[test]
+    fn test_parse_roles_and_session_duration() {
+        let assertion = r#"<saml2p:Response xmlns:saml2p="urn:oasis:names:tc:SAML:2.0:protocol" xmlns:saml2="urn:oasis:names:tc:SAML:2.0:assertion">
+            <saml2:Assertion>
+                <saml2:AttributeStatement>
+                    <saml2:Attribute Name="https://aws.amazon.com/SAML/Attributes/Role">
+                        <saml2:AttributeValue>arn:aws:iam::000222111000:saml-provider/Okta,arn:aws:iam::000222111000:role/Admin</saml2:AttributeValue>
+                        <saml2:AttributeValue>arn:aws:iam::000222111000:saml-provider/Okta,arn:aws:iam::000222111000:role/ReadOnly</saml2:AttributeValue>
+                    </saml2:Attribute>
+                    <saml2:Attribute Name="https://aws.amazon.com/SAML/Attributes/SessionDuration">
+                        <saml2:AttributeValue>43200</saml2:AttributeValue>
+                    </saml2:Attribute>
+                </saml2:AttributeStatement>
+            </saml2:Assertion>
+        </saml2p:Response>"#;
+
+        let parser = OktaAwsSamlParser::new(saml_page(assertion)).unwrap();
+        let credentials = parser.credentials().unwrap();
+
+        assert_eq!(credentials.len(), 2);
+        assert_eq!(
+            credentials[1].role_arn,
+            "arn:aws:iam::000222111000:role/ReadOnly"
+        );
+        assert_eq!(
+            credentials[1].principal_arn,
+            "arn:aws:iam::000222111000:saml-provider/Okta"
+        );
+        assert_eq!(parser.session_duration().unwrap(), Some(43200));
+    }
+
+    #[test]
+    fn test_missing_session_duration() {
+        let assertion = r#"<saml2p:Response xmlns:saml2p="urn:oasis:names:tc:SAML:2.0:protocol" xmlns:saml2="urn:oasis:names:tc:SAML:2.0:assertion">
+            <saml2:Attribute Name="https://aws.amazon.com/SAML/Attributes/Role">
+                <saml2:AttributeValue>arn:aws:iam::000222111000:saml-provider/Okta,arn:aws:iam::000222111000:role/Admin</saml2:AttributeValue>
+            </saml2:Attribute>
+        </saml2p:Response>"#;
+
+        let parser = OktaAwsSamlParser::new(saml_page(assertion)).unwrap();
+
+        assert_eq!(parser.session_duration().unwrap(), None);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::error::{Error, Result};
+use crate::http::api_client::{AcceptType, ApiClient};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use time::format_description::well_known::Rfc3339;
+use time::{Duration, OffsetDateTime};
+use url::Url;
+
+/// Sessions expiring within this time are refreshed before they're used.
+const REFRESH_THRESHOLD: Duration = Duration::minutes(10);
+
+/// An Okta session, which lets c9s fetch SAML assertions without authenticating again.
+///
+/// <https://developer.okta.com/docs/reference/api/sessions/#session-object>
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OktaSession {
+    id: String,
+    expires_at: String,
+}
+
+impl OktaSession {
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    pub fn expires_at(&self) -> String {
+        self.expires_at.clone()
+    }
+
+    /// The headers authenticating a request with this session, like Okta's `sid` cookie does for
+    /// browsers.
+    pub fn headers(&self) -> HashMap<String, String> {
+        let mut headers = HashMap::new();
+        headers.insert(String::from("cookie"), format!("sid={}", self.id));
+        headers
+    }
+
+    /// Whether the session expires within `duration`, sessions with an unreadable expiry are
+    /// treated as expired.
+    fn expires_within(&self, duration: Duration) -> bool {
+        match OffsetDateTime::parse(self.expires_at.as_str(), &Rfc3339) {
+            Ok(expires_at) => expires_at - OffsetDateTime::now_utc() < duration,
+            Err(_) => true,
+        }
+    }
+}
+
+/// Creates, validates and refreshes Okta sessions.
+pub struct SessionClient {
+    client: ApiClient,
+}
+
+impl SessionClient {
+    /// Generates a new [`SessionClient`] object.
+    pub fn new() -> Result<SessionClient> {
+        let client = ApiClient::new()?;
+        Ok(SessionClient { client })
+    }
+
+    /// Exchanges the one-time session token of an authentication for a session.
+    ///
+    /// <https://developer.okta.com/docs/reference/api/sessions/#create-session-with-session-token>
+    pub async fn create(&self, app_url: &str, session_token: String) -> Result<OktaSession> {
+        let url = api_url(app_url, "/api/v1/sessions")?;
+        let json = serde_json::json!({
+            "sessionToken": session_token,
+        });
+
+        let response = self.client.post_json(url.as_str(), &json).await?;
+        let body = response.text().await?;
+
+        Ok(serde_json::from_str(body.as_str())?)
+    }
+
+    /// Checks that Okta still accepts the session, refreshing it when it expires soon.
+    ///
+    /// Returns `None` when the session expired or was ended, so that the user has to authenticate
+    /// again.
+    pub async fn resume(&self, app_url: &str, session: OktaSession) -> Result<Option<OktaSession>> {
+        if session.expires_within(Duration::ZERO) {
+            return Ok(None);
+        }
+
+        let url = api_url(app_url, "/api/v1/sessions/me")?;
+        let response = self
+            .client
+            .get(url, None, Some(session.headers()), AcceptType::Json)
+            .await;
+        let current: OktaSession = match invalid_session_as_none(response)? {
+            Some(response) => serde_json::from_str(response.text().await?.as_str())?,
+            None => return Ok(None),
+        };
+
+        if !current.expires_within(REFRESH_THRESHOLD) {
+            return Ok(Some(current));
+        }
+
+        let url = api_url(app_url, "/api/v1/sessions/me/lifecycle/refresh")?;
+        let response = self
+            .client
+            .post_json_with_headers(
+                url.as_str(),
+                &serde_json::json!({}),
+                Some(current.headers()),
+            )
+            .await;
+
+        match invalid_session_as_none(response)? {
+            Some(response) => Ok(Some(serde_json::from_str(response.text().await?.as_str())?)),
+            None => Ok(None),
+        }
+    }
+}
+
+pub(crate) fn api_url(app_url: &str, path: &str) -> Result<String> {
+    let mut url = Url::parse(app_url)?;
+    url.set_path(path);
+    url.set_query(None);
+
+    Ok(url.to_string())
+}
+
+/// Okta answers with 404 for sessions it doesn't know (anymore).
+fn invalid_session_as_none(
+    response: Result<reqwest::Response>,
+) -> Result<Option<reqwest::Response>> {
+    match response {
+        Ok(response) => Ok(Some(response)),
+        Err(Error::HttpStatus {
+            code: 401 | 403 | 404,
+            ..
+        }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::MockServer;
+
+    fn session(id: &str, expires_in: Duration) -> OktaSession {
+        OktaSession {
+            id: String::from(id),
+            expires_at: (OffsetDateTime::now_utc() + expires_in)
+                .format(&Rfc3339)
+                .unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_session() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/api/v1/sessions")
+                .json_body(serde_json::json!({ "sessionToken": "TheToken" }));
+            then.status(200).header("content-type", "application/json").body(
+                r#"{"id":"TheSession","userId":"User","expiresAt":"2030-01-01T00:00:00.000Z","status":"ACTIVE"}"#,
+            );
+        });
+
+        let client = SessionClient::new().unwrap();
+        let session = client
+            .create(
+                server.url("/home/amazon_aws/0oa/272").as_str(),
+                String::from("TheToken"),
+            )
+            .await
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(session.id(), "TheSession");
+        assert_eq!(session.expires_at(), "2030-01-01T00:00:00.000Z");
+    }
+
+    #[tokio::test]
+    async fn test_resume_valid_session() {
+        let current = session("TheSession", Duration::hours(1));
+        let body = serde_json::to_string(&current).unwrap();
+
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v1/sessions/me")
+                .header("cookie", "sid=TheSession");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(body);
+        });
+
+        let client = SessionClient::new().unwrap();
+        let resumed = client
+            .resume(server.url("/app").as_str(), current)
+            .await
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(resumed.unwrap().id(), "TheSession");
+    }
+
+    #[tokio::test]
+    async fn test_resume_refreshes_expiring_session() {
+        let current = session("TheSession", Duration::minutes(2));
+        let refreshed = session("TheSession", Duration::hours(2));
+        let current_body = serde_json::to_string(&current).unwrap();
+        let refreshed_body = serde_json::to_string(&refreshed).unwrap();
+
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method("GET").path("/api/v1/sessions/me");
+            then.status(200).body(current_body);
+        });
+        let refresh_mock = server.mock(|when, then| {
+            when.method("POST")
+                .path("/api/v1/sessions/me/lifecycle/refresh")
+                .header("cookie", "sid=TheSession");
+            then.status(200).body(refreshed_body);
+        });
+
+        let client = SessionClient::new().unwrap();
+        let resumed = client
+            .resume(server.url("/app").as_str(), current)
+            .await
+            .unwrap()
+            .unwrap();
+
+        refresh_mock.assert();
+        assert_eq!(resumed.expires_at(), refreshed.expires_at());
+    }
+
+    #[tokio::test]
+    async fn test_resume_ended_session() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method("GET").path("/api/v1/sessions/me");
+            then.status(404);
+        });
+
+        let client = SessionClient::new().unwrap();
+        let resumed = client
+            .resume(
+                server.url("/app").as_str(),
+                session("TheSession", Duration::hours(1)),
+            )
+            .await
+            .unwrap();
+
+        assert!(resumed.is_none());
+    }
+}
@@ -1,9 +1,13 @@
+use crate::aws::sts::{RoleHop, StsOptions};
+use crate::aws::{Partition, Role, SsoPortalEndpoint};
+use crate::error::{Error, Result};
 use crate::okta::okta_client::MfaSelection;
-use anyhow::{anyhow, Result};
-use clap::ValueEnum;
+use clap::{Args, ValueEnum};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use time::Duration;
 use url::Url;
 
 #[derive(Serialize, Deserialize)]
@@ -11,19 +15,44 @@ pub struct AppConfig {
     global_settings: Option<GlobalSettings>,
     okta_aws_hosts: Option<Vec<AwsHost>>,
     okta_aws_sso_hosts: Option<Vec<AwsSsoHost>>,
+    profiles: Option<BTreeMap<String, Profile>>,
+    aws_aliases: Option<AwsAliases>,
+}
+
+/// Friendly names for AWS accounts and roles.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct AwsAliases {
+    /// Account ids and their aliases.
+    #[serde(default)]
+    accounts: BTreeMap<String, String>,
+    /// Short names and the ARNs of their roles.
+    #[serde(default)]
+    roles: BTreeMap<String, String>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct GlobalSettings {
     use_keyring: Option<bool>,
     aws_defaults: Option<AwsDefaults>,
+    /// Seconds before their expiration in which credentials are renewed.
+    refresh_window: Option<i64>,
+    /// Where sessions and credentials are cached.
+    cache_backend: Option<CacheBackend>,
+    /// The key of the encrypted file cache, when no passphrase is given.
+    cache_key_file: Option<PathBuf>,
 }
 
+/// Credentials are renewed 10 minutes before they expire by default.
+const DEFAULT_REFRESH_WINDOW: i64 = 10 * 60;
+
 impl GlobalSettings {
     fn default() -> Self {
         Self {
             use_keyring: None,
             aws_defaults: Some(AwsDefaults::default()),
+            refresh_window: None,
+            cache_backend: None,
+            cache_key_file: None,
         }
     }
 }
@@ -34,6 +63,10 @@ pub struct AwsHost {
     username: String,
     mfa: Option<String>,
     mfa_provider: Option<String>,
+    session_duration: Option<i32>,
+    authenticator: Option<OktaAuthenticator>,
+    #[serde(flatten)]
+    endpoints: AwsEndpoints,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -43,6 +76,45 @@ pub struct AwsSsoHost {
     region: String,
     mfa: Option<String>,
     mfa_provider: Option<String>,
+    authenticator: Option<OktaAuthenticator>,
+    #[serde(flatten)]
+    endpoints: AwsEndpoints,
+}
+
+/// Where credentials are requested from AWS, for accounts outside the standard partition or
+/// behind custom endpoints.
+#[derive(Args, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AwsEndpoints {
+    /// The AWS partition of the accounts, used for role ARNs and the AWS SSO portal.
+    #[clap(long, value_enum)]
+    partition: Option<Partition>,
+    /// The region used for STS, defaults to a region of the role's partition.
+    #[clap(long)]
+    sts_region: Option<String>,
+    /// Overrides the STS endpoint, e.g. for VPC endpoints.
+    #[clap(long)]
+    sts_endpoint: Option<String>,
+    /// Overrides the AWS SSO portal url, e.g. for VPC endpoints.
+    #[clap(long)]
+    sso_portal_url: Option<String>,
+}
+
+/// A named set of options for `c9s creds aws`, so they don't have to be passed every time.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Profile {
+    provider: Option<SsoProvider>,
+    app_url: Option<String>,
+    username: Option<String>,
+    role_arn: Option<String>,
+    region: Option<String>,
+    mfa: Option<String>,
+    mfa_provider: Option<String>,
+    session_duration: Option<i32>,
+    output: Option<OutputOptions>,
+    /// The roles assumed one after another, starting with the credentials of `role_arn`.
+    chain: Option<Vec<RoleHop>>,
+    #[serde(flatten)]
+    endpoints: AwsEndpoints,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default)]
@@ -59,6 +131,54 @@ pub enum SsoProvider {
     OktaAwsSso,
 }
 
+impl SsoProvider {
+    /// The name of the provider as used in the settings.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SsoProvider::OktaAws => "okta-aws",
+            SsoProvider::OktaAwsSso => "okta-aws-sso",
+        }
+    }
+}
+
+/// How c9s authenticates against Okta.
+#[derive(ValueEnum, PartialEq, Eq, Debug, Clone, Copy, Deserialize, Serialize, Default)]
+pub enum OktaAuthenticator {
+    /// The Classic authentication API, or Identity Engine if Okta disabled it.
+    #[serde(rename = "auto")]
+    #[default]
+    Auto,
+    /// The Classic authentication API (`/api/v1/authn`).
+    #[serde(rename = "classic")]
+    Classic,
+    /// The Identity Engine remediation flow (`/idp/idx`).
+    #[serde(rename = "idx")]
+    Idx,
+}
+
+/// Where c9s caches sessions and credentials.
+#[derive(ValueEnum, PartialEq, Eq, Debug, Clone, Copy, Deserialize, Serialize, Default)]
+pub enum CacheBackend {
+    /// The keyring service of the operating system, if the keyring is enabled.
+    #[serde(rename = "keyring")]
+    #[default]
+    Keyring,
+    /// A file encrypted with a passphrase or a key file.
+    #[serde(rename = "encrypted-file")]
+    EncryptedFile,
+}
+
+#[derive(ValueEnum, PartialEq, Eq, Debug, Clone, Copy, Deserialize, Serialize, Default)]
+pub enum OutputOptions {
+    #[serde(rename = "env")]
+    #[default]
+    Env,
+    #[serde(rename = "aws-profile")]
+    AwsProfile,
+    #[serde(rename = "aws-credentials-file")]
+    AwsCredentialsFile,
+}
+
 impl AppConfig {
     pub fn set_aws_defaults(&mut self, defaults: AwsDefaults) {
         let global_settings = self
@@ -89,6 +209,76 @@ impl AppConfig {
         }
     }
 
+    pub fn set_refresh_window(&mut self, seconds: i64) {
+        let global_settings = self
+            .global_settings
+            .get_or_insert(GlobalSettings::default());
+        global_settings.refresh_window = Some(seconds);
+    }
+
+    /// How long before their expiration credentials are treated as stale and renewed.
+    pub fn refresh_window(&self) -> Duration {
+        let seconds = match &self.global_settings {
+            Some(global_settings) => global_settings
+                .refresh_window
+                .unwrap_or(DEFAULT_REFRESH_WINDOW),
+            None => DEFAULT_REFRESH_WINDOW,
+        };
+
+        Duration::seconds(seconds)
+    }
+
+    pub fn set_cache_backend(&mut self, backend: CacheBackend, key_file: Option<PathBuf>) {
+        let global_settings = self
+            .global_settings
+            .get_or_insert(GlobalSettings::default());
+        global_settings.cache_backend = Some(backend);
+        global_settings.cache_key_file = key_file;
+    }
+
+    pub fn cache_backend(&self) -> CacheBackend {
+        self.global_settings
+            .as_ref()
+            .and_then(|global_settings| global_settings.cache_backend)
+            .unwrap_or_default()
+    }
+
+    /// The key of the encrypted file cache, `cache.key` in the config directory by default.
+    pub fn cache_key_file(&self) -> Result<PathBuf> {
+        match self
+            .global_settings
+            .as_ref()
+            .and_then(|global_settings| global_settings.cache_key_file.clone())
+        {
+            Some(key_file) => Ok(key_file),
+            None => Ok(AppConfig::config_dir()?.join("cache.key")),
+        }
+    }
+
+    /// The encrypted file cache, `c9s/cache.enc` in the user's cache directory.
+    pub fn cache_file(&self) -> Result<PathBuf> {
+        let cache_dir = dirs::cache_dir()
+            .ok_or_else(|| Error::Config(String::from("could not determine cache directory")))?;
+
+        Ok(cache_dir.join("c9s").join("cache.enc"))
+    }
+
+    /// The account names learned from AWS SSO, `c9s/accounts.json` in the user's cache directory.
+    pub fn learned_accounts_file(&self) -> Result<PathBuf> {
+        let cache_dir = dirs::cache_dir()
+            .ok_or_else(|| Error::Config(String::from("could not determine cache directory")))?;
+
+        Ok(cache_dir.join("c9s").join("accounts.json"))
+    }
+
+    /// The index of cached secrets, `c9s/index.json` in the user's cache directory.
+    pub fn cache_index_file(&self) -> Result<PathBuf> {
+        let cache_dir = dirs::cache_dir()
+            .ok_or_else(|| Error::Config(String::from("could not determine cache directory")))?;
+
+        Ok(cache_dir.join("c9s").join("index.json"))
+    }
+
     pub fn add_aws_host(&mut self, host: AwsHost) {
         let hosts = self.okta_aws_hosts.get_or_insert(vec![]);
 
@@ -96,6 +286,9 @@ impl AppConfig {
             Some(existing) => {
                 existing.username = host.username;
                 existing.mfa = host.mfa;
+                existing.session_duration = host.session_duration;
+                existing.authenticator = host.authenticator;
+                existing.endpoints = host.endpoints;
             }
             None => {
                 hosts.push(host);
@@ -111,6 +304,8 @@ impl AppConfig {
                 existing.username = host.username;
                 existing.region = host.region;
                 existing.mfa = host.mfa;
+                existing.authenticator = host.authenticator;
+                existing.endpoints = host.endpoints;
             }
             None => {
                 hosts.push(host);
@@ -141,14 +336,39 @@ impl AppConfig {
         }
     }
 
+    pub fn add_profile(&mut self, name: String, profile: Profile) {
+        let profiles = self.profiles.get_or_insert(BTreeMap::new());
+        profiles.insert(name, profile);
+    }
+
+    pub fn remove_profile(&mut self, name: &str) -> Option<Profile> {
+        self.profiles.as_mut()?.remove(name)
+    }
+
+    pub fn find_profile(&self, name: &str) -> Option<Profile> {
+        self.profiles.as_ref()?.get(name).cloned()
+    }
+
+    pub fn profiles(&self) -> BTreeMap<String, Profile> {
+        self.profiles.clone().unwrap_or_default()
+    }
+
+    pub fn aws_aliases(&self) -> AwsAliases {
+        self.aws_aliases.clone().unwrap_or_default()
+    }
+
+    pub fn set_aws_aliases(&mut self, aliases: AwsAliases) {
+        self.aws_aliases = Some(aliases);
+    }
+
     pub fn read_config() -> Result<Self> {
         let config_file = AppConfig::config_file()?;
         if !Path::new(&config_file).exists() {
             fs::write(config_file.clone(), "")?;
         }
-        let config_contents = fs::read(config_file)?;
-        let config_contents = String::from_utf8(config_contents)?;
-        let config: AppConfig = toml::from_str(config_contents.as_str())?;
+        let config_contents = fs::read_to_string(config_file)?;
+        let config: AppConfig =
+            toml::from_str(config_contents.as_str()).map_err(|e| Error::Config(e.to_string()))?;
 
         Ok(config)
     }
@@ -159,7 +379,7 @@ impl AppConfig {
 
         let config_file = AppConfig::config_file()?;
 
-        let toml = toml::to_string(&self)?;
+        let toml = toml::to_string(&self).map_err(|e| Error::Config(e.to_string()))?;
         fs::write(config_file, toml).expect("Unable to write file");
 
         Ok(())
@@ -167,7 +387,7 @@ impl AppConfig {
 
     fn config_dir() -> Result<PathBuf> {
         let config_dir = dirs::config_dir()
-            .ok_or_else(|| anyhow!("could not determine config directory"))?
+            .ok_or_else(|| Error::Config(String::from("could not determine config directory")))?
             .join("c9s");
         fs::create_dir_all(config_dir.clone())?;
 
@@ -187,25 +407,22 @@ impl AwsHost {
         username: String,
         mfa: Option<String>,
         mfa_provider: Option<String>,
+        session_duration: Option<i32>,
+        authenticator: Option<OktaAuthenticator>,
+        endpoints: AwsEndpoints,
     ) -> Result<Self> {
-        let mut app_url = Url::parse(app_url.as_str())?;
-
-        // remove query
-        app_url.set_query(None);
-
-        // remove trailing slash
-        app_url
-            .path_segments_mut()
-            .map_err(|_| anyhow!("cannot be base"))?
-            .pop_if_empty();
+        let app_url = normalize_app_url(app_url)?;
 
         MfaSelection::validate(mfa.clone())?;
 
         Ok(AwsHost {
-            app_url: String::from(app_url),
+            app_url,
             mfa,
             username,
             mfa_provider,
+            session_duration,
+            authenticator,
+            endpoints,
         })
     }
 
@@ -216,6 +433,19 @@ impl AwsHost {
     pub fn username(&self) -> String {
         self.username.clone()
     }
+
+    /// The session duration in seconds requested from STS.
+    pub fn session_duration(&self) -> Option<i32> {
+        self.session_duration
+    }
+
+    pub fn authenticator(&self) -> OktaAuthenticator {
+        self.authenticator.unwrap_or_default()
+    }
+
+    pub fn endpoints(&self) -> AwsEndpoints {
+        self.endpoints.clone()
+    }
 }
 
 impl AwsSsoHost {
@@ -225,26 +455,21 @@ impl AwsSsoHost {
         region: String,
         mfa: Option<String>,
         mfa_provider: Option<String>,
+        authenticator: Option<OktaAuthenticator>,
+        endpoints: AwsEndpoints,
     ) -> Result<Self> {
-        let mut app_url = Url::parse(app_url.as_str())?;
-
-        // remove query
-        app_url.set_query(None);
-
-        // remove trailing slash
-        app_url
-            .path_segments_mut()
-            .map_err(|_| anyhow!("cannot be base"))?
-            .pop_if_empty();
+        let app_url = normalize_app_url(app_url)?;
 
         MfaSelection::validate(mfa.clone())?;
 
         Ok(AwsSsoHost {
-            app_url: String::from(app_url),
+            app_url,
             username,
             region,
             mfa,
             mfa_provider,
+            authenticator,
+            endpoints,
         })
     }
 
@@ -259,6 +484,269 @@ impl AwsSsoHost {
     pub fn region(&self) -> String {
         self.region.clone()
     }
+
+    pub fn authenticator(&self) -> OktaAuthenticator {
+        self.authenticator.unwrap_or_default()
+    }
+
+    pub fn endpoints(&self) -> AwsEndpoints {
+        self.endpoints.clone()
+    }
+}
+
+impl AwsAliases {
+    pub fn accounts(&self) -> BTreeMap<String, String> {
+        self.accounts.clone()
+    }
+
+    pub fn roles(&self) -> BTreeMap<String, String> {
+        self.roles.clone()
+    }
+
+    pub fn set_account(&mut self, account_id: String, alias: String) -> Result<()> {
+        if alias.contains('/') {
+            return Err(Error::Config(format!(
+                "alias {alias} can't contain a slash"
+            )));
+        }
+        self.accounts.insert(account_id, alias);
+
+        Ok(())
+    }
+
+    pub fn set_role(&mut self, name: String, role_arn: String) -> Result<()> {
+        Role::from_arn(&role_arn)?;
+        self.roles.insert(name, role_arn);
+
+        Ok(())
+    }
+
+    /// Removes a role's short name, or an account alias by its name or its account id.
+    pub fn remove(&mut self, name: &str) -> bool {
+        if self.roles.remove(name).is_some() {
+            return true;
+        }
+
+        let before = self.accounts.len();
+        self.accounts
+            .retain(|account_id, alias| account_id != name && alias != name);
+        self.accounts.len() != before
+    }
+
+    /// Adds the learned account names as aliases of the accounts which don't have one.
+    pub fn with_learned_accounts(mut self, learned: BTreeMap<String, String>) -> Self {
+        for (account_id, account_name) in learned {
+            self.accounts.entry(account_id).or_insert(account_name);
+        }
+        self
+    }
+
+    /// Resolves a role's short name, or `alias/role-name` and `account-id/role-name`, to the
+    /// role's ARN. A role ARN is returned as it is.
+    pub fn resolve_role(&self, name: &str, partition: Partition) -> Result<String> {
+        if let Some(role_arn) = self.roles.get(name) {
+            return Ok(role_arn.clone());
+        }
+        if name.starts_with("arn:") {
+            return Ok(Role::from_arn(name)?.role_arn());
+        }
+
+        let (account, role_name) = name
+            .split_once('/')
+            .ok_or_else(|| Error::RoleNotFound(name.to_string()))?;
+        let account_id = self
+            .accounts
+            .iter()
+            .find(|(_, alias)| alias.as_str() == account)
+            .map(|(account_id, _)| account_id.clone())
+            .or_else(|| {
+                account
+                    .chars()
+                    .all(|c| c.is_ascii_digit())
+                    .then(|| account.to_string())
+            })
+            .ok_or_else(|| Error::RoleNotFound(name.to_string()))?;
+
+        Ok(format!(
+            "arn:{}:iam::{}:role/{}",
+            partition.name(),
+            account_id,
+            role_name
+        ))
+    }
+
+    /// The friendly name of a role: its short name, or `alias/role-name` when its account has
+    /// an alias.
+    pub fn display_name(&self, role_arn: &str) -> Option<String> {
+        if let Some((name, _)) = self.roles.iter().find(|(_, arn)| arn.as_str() == role_arn) {
+            return Some(name.clone());
+        }
+
+        let role = Role::from_arn(role_arn).ok()?;
+        let alias = self.accounts.get(&role.account_id())?;
+        Some(format!("{}/{}", alias, role.role_name()))
+    }
+}
+
+impl AwsEndpoints {
+    pub fn new(
+        partition: Option<Partition>,
+        sts_region: Option<String>,
+        sts_endpoint: Option<String>,
+        sso_portal_url: Option<String>,
+    ) -> Self {
+        Self {
+            partition,
+            sts_region,
+            sts_endpoint,
+            sso_portal_url,
+        }
+    }
+
+    /// Fills every value which isn't set from `other`.
+    pub fn or(&self, other: &AwsEndpoints) -> Self {
+        Self {
+            partition: self.partition.or(other.partition),
+            sts_region: self.sts_region.clone().or_else(|| other.sts_region.clone()),
+            sts_endpoint: self
+                .sts_endpoint
+                .clone()
+                .or_else(|| other.sts_endpoint.clone()),
+            sso_portal_url: self
+                .sso_portal_url
+                .clone()
+                .or_else(|| other.sso_portal_url.clone()),
+        }
+    }
+
+    pub fn partition(&self) -> Option<Partition> {
+        self.partition
+    }
+
+    pub fn sts_region(&self) -> Option<String> {
+        self.sts_region.clone()
+    }
+
+    pub fn sts_endpoint(&self) -> Option<String> {
+        self.sts_endpoint.clone()
+    }
+
+    pub fn sso_portal_url(&self) -> Option<String> {
+        self.sso_portal_url.clone()
+    }
+
+    pub fn sts_options(&self) -> StsOptions {
+        StsOptions::new(self.sts_region.clone(), self.sts_endpoint.clone())
+    }
+
+    pub fn sso_portal_endpoint(&self, region: String) -> SsoPortalEndpoint {
+        SsoPortalEndpoint::new(
+            region,
+            self.partition.unwrap_or_default(),
+            self.sso_portal_url.clone(),
+        )
+    }
+}
+
+impl Profile {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        provider: Option<SsoProvider>,
+        app_url: Option<String>,
+        username: Option<String>,
+        role_arn: Option<String>,
+        region: Option<String>,
+        mfa: Option<String>,
+        mfa_provider: Option<String>,
+        session_duration: Option<i32>,
+        output: Option<OutputOptions>,
+        endpoints: AwsEndpoints,
+    ) -> Result<Self> {
+        let app_url = app_url.map(normalize_app_url).transpose()?;
+
+        MfaSelection::validate(mfa.clone())?;
+
+        Ok(Profile {
+            provider,
+            app_url,
+            username,
+            role_arn,
+            region,
+            mfa,
+            mfa_provider,
+            session_duration,
+            output,
+            chain: None,
+            endpoints,
+        })
+    }
+
+    /// Assumes the roles of the chain with the credentials of the profile's role.
+    pub fn with_chain(mut self, chain: Vec<RoleHop>) -> Self {
+        self.chain = (!chain.is_empty()).then_some(chain);
+        self
+    }
+
+    pub fn provider(&self) -> Option<SsoProvider> {
+        self.provider
+    }
+
+    pub fn app_url(&self) -> Option<String> {
+        self.app_url.clone()
+    }
+
+    pub fn username(&self) -> Option<String> {
+        self.username.clone()
+    }
+
+    pub fn role_arn(&self) -> Option<String> {
+        self.role_arn.clone()
+    }
+
+    pub fn region(&self) -> Option<String> {
+        self.region.clone()
+    }
+
+    pub fn mfa(&self) -> Option<String> {
+        self.mfa.clone()
+    }
+
+    pub fn mfa_provider(&self) -> Option<String> {
+        self.mfa_provider.clone()
+    }
+
+    pub fn session_duration(&self) -> Option<i32> {
+        self.session_duration
+    }
+
+    pub fn output(&self) -> Option<OutputOptions> {
+        self.output
+    }
+
+    pub fn endpoints(&self) -> AwsEndpoints {
+        self.endpoints.clone()
+    }
+
+    pub fn chain(&self) -> Vec<RoleHop> {
+        self.chain.clone().unwrap_or_default()
+    }
+}
+
+/// Removes the query and the trailing slash from an app url, so that it can be used as a key.
+fn normalize_app_url(app_url: String) -> Result<String> {
+    let mut app_url = Url::parse(app_url.as_str())
+        .map_err(|e| Error::Config(format!("invalid app url {app_url}: {e}")))?;
+
+    // remove query
+    app_url.set_query(None);
+
+    // remove trailing slash
+    app_url
+        .path_segments_mut()
+        .map_err(|_| Error::Config(String::from("app url cannot be a base")))?
+        .pop_if_empty();
+
+    Ok(String::from(app_url))
 }
 
 pub trait OktaMfa {
@@ -295,3 +783,188 @@ impl AwsDefaults {
         self.sso_provider
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_role_aliases() {
+        let mut aliases = AwsAliases::default();
+        aliases
+            .set_account(String::from("000222111000"), String::from("prod"))
+            .unwrap();
+        aliases
+            .set_role(
+                String::from("admin"),
+                String::from("arn:aws:iam::000333111000:role/Admin"),
+            )
+            .unwrap();
+
+        assert_eq!(
+            aliases.resolve_role("prod/Admin", Partition::Aws).unwrap(),
+            "arn:aws:iam::000222111000:role/Admin"
+        );
+        assert_eq!(
+            aliases
+                .resolve_role("000444111000/ReadOnly", Partition::AwsUsGov)
+                .unwrap(),
+            "arn:aws-us-gov:iam::000444111000:role/ReadOnly"
+        );
+        assert_eq!(
+            aliases.resolve_role("admin", Partition::Aws).unwrap(),
+            "arn:aws:iam::000333111000:role/Admin"
+        );
+        assert!(aliases
+            .resolve_role("staging/Admin", Partition::Aws)
+            .is_err());
+
+        assert_eq!(
+            aliases.display_name("arn:aws:iam::000222111000:role/Admin"),
+            Some(String::from("prod/Admin"))
+        );
+        assert_eq!(
+            aliases.display_name("arn:aws:iam::000333111000:role/Admin"),
+            Some(String::from("admin"))
+        );
+        assert_eq!(
+            aliases.display_name("arn:aws:iam::000444111000:role/Admin"),
+            None
+        );
+
+        assert!(aliases.remove("prod"));
+        assert!(aliases.accounts().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_role_arn() {
+        let aliases = AwsAliases::default();
+
+        assert_eq!(
+            aliases
+                .resolve_role("arn:aws:iam::000222111000:role/Admin", Partition::AwsUsGov)
+                .unwrap(),
+            "arn:aws:iam::000222111000:role/Admin"
+        );
+        assert!(aliases
+            .resolve_role("arn:aws:iam::000222111000:user/Admin", Partition::Aws)
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_profiles() {
+        let config = r#"
+            [profiles.prod-admin]
+            provider = "okta-aws-sso"
+            app_url = "https://domain.okta.com/home/amazon_aws_sso/123"
+            role_arn = "arn:aws:iam::000222111000:role/Admin"
+            region = "eu-central-1"
+            output = "aws-profile"
+            partition = "aws-us-gov"
+            sts_region = "us-gov-east-1"
+
+            [[profiles.prod-admin.chain]]
+            role_arn = "arn:aws-us-gov:iam::000333111000:role/Spoke"
+            external_id = "TheExternalId"
+            tags = { team = "platform" }
+        "#;
+
+        let config: AppConfig = toml::from_str(config).unwrap();
+        let profile = config.find_profile("prod-admin").unwrap();
+
+        assert_eq!(profile.provider(), Some(SsoProvider::OktaAwsSso));
+        assert_eq!(
+            profile.role_arn(),
+            Some(String::from("arn:aws:iam::000222111000:role/Admin"))
+        );
+        assert_eq!(profile.region(), Some(String::from("eu-central-1")));
+        assert_eq!(profile.output(), Some(OutputOptions::AwsProfile));
+        assert_eq!(profile.endpoints().partition(), Some(Partition::AwsUsGov));
+        assert_eq!(
+            profile.endpoints().sts_region(),
+            Some(String::from("us-gov-east-1"))
+        );
+        assert_eq!(profile.endpoints().sso_portal_url(), None);
+        assert_eq!(profile.username(), None);
+        assert_eq!(
+            profile.chain(),
+            vec![RoleHop::new(
+                String::from("arn:aws-us-gov:iam::000333111000:role/Spoke"),
+                Some(String::from("TheExternalId")),
+                None,
+                BTreeMap::from([(String::from("team"), String::from("platform"))]),
+            )
+            .unwrap()]
+        );
+        assert!(config.find_profile("dev").is_none());
+    }
+
+    #[test]
+    fn test_readd_host_with_authenticator() {
+        let mut settings: AppConfig = toml::from_str("").unwrap();
+        let aws_host = |authenticator| {
+            AwsHost::new(
+                String::from("https://domain.okta.com/home/amazon_aws/123"),
+                String::from("the-user"),
+                None,
+                None,
+                None,
+                authenticator,
+                AwsEndpoints::default(),
+            )
+            .unwrap()
+        };
+        let sso_host = |authenticator| {
+            AwsSsoHost::new(
+                String::from("https://domain.okta.com/home/amazon_aws_sso/123"),
+                String::from("the-user"),
+                String::from("eu-central-1"),
+                None,
+                None,
+                authenticator,
+                AwsEndpoints::default(),
+            )
+            .unwrap()
+        };
+
+        settings.add_aws_host(aws_host(None));
+        settings.add_aws_host(aws_host(Some(OktaAuthenticator::Idx)));
+        settings.add_aws_sso_host(sso_host(Some(OktaAuthenticator::Classic)));
+        settings.add_aws_sso_host(sso_host(Some(OktaAuthenticator::Idx)));
+
+        assert_eq!(settings.okta_aws_hosts.as_ref().unwrap().len(), 1);
+        assert_eq!(
+            settings.aws_hosts().unwrap().authenticator(),
+            OktaAuthenticator::Idx
+        );
+        assert_eq!(settings.okta_aws_sso_hosts.as_ref().unwrap().len(), 1);
+        assert_eq!(
+            settings.aws_sso_hosts().unwrap().authenticator(),
+            OktaAuthenticator::Idx
+        );
+    }
+
+    #[test]
+    fn test_profile_normalizes_app_url() {
+        let profile = Profile::new(
+            None,
+            Some(String::from(
+                "https://domain.okta.com/home/amazon_aws/123/?foo=bar",
+            )),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            AwsEndpoints::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            profile.app_url(),
+            Some(String::from("https://domain.okta.com/home/amazon_aws/123"))
+        );
+    }
+}